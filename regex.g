@@ -1,8 +1,15 @@
 // @Parser{
 
-start: Regex=r "!" {r}
+// spaces are characters of the regex
+%skip;
+
+start: Alternatives=a {if a.len()==1 {a.pop().unwrap()} else {vec![Regexpr::Match(Term::Or(a))]}}
      -> Vec<Regexpr>;
 
+Alternatives: Alternatives=stack "|" Regex=r {stack.push(r); stack}
+    | Regex=r {vec![r]}
+    -> Branches;
+
 Regex: Regex=stack EXPR=e {stack.push(e); stack}
     | EXPR=e {vec![e]}
     -> Vec<Regexpr>;
@@ -10,28 +17,58 @@ Regex: Regex=stack EXPR=e {stack.push(e); stack}
 EXPR: TERM=t              {Regexpr::Match(t)       }
     | TERM=t "*"          {Regexpr::Any(t)         }
     | TERM=t "?"          {Regexpr::Maybe(t)       }
-    | TERM=t "+"          {Regexpr::More(t.clone())}
+    | TERM=t "+"          {Regexpr::More(t)        }
+    | TERM=t r"\{[0-9]+(,[0-9]*)?\}"=c {let (min, max) = count(c); Regexpr::Repeat(t, min, max)}
     -> Regexpr;
 
 TERM: CHR=s {Term::Char(s)}
-    | "[^" SYMS=s "]" {Term::NGroup(s)}
-    | "[" SYMS=s "]" {Term::Group(s)}
-    | "(" Pattern=p ")" {p}
+    | "-"=s {Term::Char('-')}
+    | "."=s {Term::NGroup(vec!['\n'])}
+    | r"\\[dws]"=s {Term::Group(class(s))}
+    | r"\\[DWS]"=s {Term::NGroup(class(s))}
+    | "[^" CLASS=s "]" {Term::NGroup(s)}
+    | "[" CLASS=s "]" {Term::Group(s)}
+    | "(" Alternatives=a ")" {if a.len()==1 {Term::Pattern(a.pop().unwrap())} else {Term::Or(a)}}
     -> Term;
 
-Pattern: Regex=r {Term::Pattern(r)}
-    |    Regex=a "|" Regex=b {Term::Or(a,b)}
-    -> Term;
+// a "-" at the start or the end of a class is literal
+CLASS: BODY=s {s}
+     | "-" BODY=s {s.push('-'); s}
+     | "-" {vec!['-']}
+     -> Vec<char>;
+
+BODY: SYMS=s {s}
+    | SYMS=stack TAIL=s {stack.extend(s); stack}
+    | TAIL=s {s}
+    -> Vec<char>;
 
-SYMS: SYMS=stack SYM=s {stack.push(s); stack}
+TAIL: CCHR=c "-" {vec![c, '-']}
+    | CCHR=a "-" CCHR=b "-" {let mut s: Vec<char> = (a..=b).collect(); s.push('-'); s}
+    | r"\\[dws]"=s "-" {let mut s = class(s); s.push('-'); s}
+    -> Vec<char>;
+
+SYMS: SYMS=stack SYM=s {stack.extend(s); stack}
     | SYM=s {s} ->Vec<char>;
 
-SYM: CHR=c {vec![c]}
-   | CHR=a "-" CHR=b {a..=b.collect()}
+SYM: CCHR=c {vec![c]}
+   | CCHR=a "-" CCHR=b {(a..=b).collect()}
+   | r"\\[dws]"=s {class(s)}
    -> Vec<char>;
 
-CHR: r"[^\[\]\(\)\.\\\+\*\|\?]"=s {s.chars().next().unwrap()}
-   | r"\\[\[\]\(\)\.\\\+\*\|\?]"=s {s.chars().nth(1).unwrap()}
+// operators are plain chars inside a class
+CCHR: CHR=c {c}
+    | "+" {'+'}
+    | "*" {'*'}
+    | "?" {'?'}
+    | "|" {'|'}
+    | "." {'.'}
+    | "(" {'('}
+    | ")" {')'}
+    -> char;
+
+CHR: r"[^\[\]\(\)\.\\\+\*\|\?\-]"=s {s.chars().next().unwrap()}
+   | r"\\[^nrt0dwsDWS]"=s {s.chars().nth(1).unwrap()}
+   | r"\\[nrt0]"=s {escape(s)}
     -> char;
 
 // }->MLCC(Gast)->{
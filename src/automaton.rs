@@ -3,6 +3,7 @@ use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::collections::hash_map::Entry;
+use serde::{Serialize, Deserialize};
 
 use crate::lr::*;
 use crate::parser;
@@ -11,6 +12,9 @@ type IdxState = usize;
 type IdxToken = usize;
 type IdxReduction = usize;
 type IdxComponent = usize;
+type IdxNonterminal = usize;
+// actions and gotos of a state
+type Row = (Vec<(IdxToken, Action)>, Vec<(IdxNonterminal, IdxState)>);
 
 
 #[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -24,36 +28,71 @@ type StateImpl = BTreeSet<Path>;
 type IdxRule = usize;
 type IdxReductend = usize;
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Reduction {
+    pub nonterminal: IdxNonterminal,
+    pub len: usize,
     pub task: Option<ReductionTask>,
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ReductionTask{
     pub code: Rc<str>,
     pub args: Vec<Option<Arg>>,
     pub return_type: Rc<str>,
+    // the code returns a Result of the return type
+    #[serde(default)]
+    pub fallible: bool,
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Arg {
     pub identifier: Rc<str>,
     pub arg_type: Rc<str>
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Action {
     Shift(IdxState),
     Reduce(IdxReduction),
-    Halt
+    Accept,
+    Error
+}
+impl Action {
+    fn map_state(&self, f: impl Fn(IdxState) -> IdxState) -> Self {
+        match self {
+            Action::Shift(s) => Action::Shift(f(*s)),
+            a => a.clone()
+        }
+    }
 }
 
 #[derive(Default)]
 pub struct State {
     pub position: Positions,
     pub lookahead: HashMap<IdxToken, Action>,
-    pub goto: HashMap<IdxReduction, IdxState>,
+    pub goto: HashMap<IdxNonterminal, IdxState>,
+}
+impl State {
+    // missing entries are syntax errors
+    pub fn action(&self, token: IdxToken) -> Action {
+        self.lookahead.get(&token).cloned().unwrap_or(Action::Error)
+    }
+    // terminals with a valid action, sorted
+    pub fn expected(&self) -> Vec<IdxToken> {
+        let mut expected: Vec<_> = self.lookahead.keys().cloned().collect();
+        expected.sort();
+        expected
+    }
+    // sorted action and goto row with all target states passed through f
+    fn row(&self, f: impl Fn(IdxState) -> IdxState) -> Row {
+        let mut lookahead: Vec<_> = self.lookahead.iter().map(|(t, a)| (*t, a.map_state(&f))).collect();
+        let mut goto: Vec<_> = self.goto.iter().map(|(r, s)| (*r, f(*s))).collect();
+        lookahead.sort();
+        goto.sort();
+        (lookahead, goto)
+    }
 }
 
 macro_rules! make_automanton {
@@ -90,9 +129,41 @@ macro_rules! make_automanton {
 }
 make_automanton!{
     terminals: |Token, Token|,
+    nonterminals: |Rc<str>, IdxRule|,
     reductions: |Reduction, ReductendPosition|
 }
 
+impl Automaton {
+    // merge equivalent states by partition refinement
+    // states end up in the same block if their rows match after mapping all targets to their blocks
+    // blocks are numbered by first occurrence, so the start state stays 0
+    pub fn minimize(&mut self) {
+        let mut partition = vec![0; self.states.len()];
+        let mut count = 1;
+        loop {
+            let mut blocks = HashMap::new();
+            let next: Vec<usize> = self.states.iter().enumerate().map(|(i, state)| {
+                let len = blocks.len();
+                *blocks.entry((partition[i], state.row(|s| partition[s]))).or_insert(len)
+            }).collect();
+            partition = next;
+            if blocks.len() == count {
+                break;
+            }
+            count = blocks.len();
+        }
+
+        let mut states: Vec<State> = (0..count).map(|_| State::default()).collect();
+        for (i, state) in std::mem::take(&mut self.states).into_iter().enumerate() {
+            let merged = &mut states[partition[i]];
+            merged.position.merge(state.position);
+            merged.lookahead = state.lookahead.iter().map(|(t, a)| (*t, a.map_state(|s| partition[s]))).collect();
+            merged.goto = state.goto.iter().map(|(r, s)| (*r, partition[*s])).collect();
+        }
+        self.states = states;
+    }
+}
+
 
 macro_rules! vecmap {
     ($self:ident, $name:ident, $e:expr) => {
@@ -129,8 +200,22 @@ impl AutomatonBuilder<'_> {
         vecmap!(self, terminals, Token::EOF); // Token::EOF == 0
 
         let start = lr.start.clone();
-        let _start_idx = self.bake_state(lr, start)?;
-
+        let start_idx = self.bake_state(lr, start)?;
+
+        // accept on Token::EOF after the start rule was reduced in the start state
+        let start_rule = self.make_nonterminal(Position::rule_index(self.rules, "start")?);
+        let accept_idx = match self.automaton.states[start_idx].goto.get(&start_rule) {
+            Some(idx) => *idx,
+            None => {
+                let idx = self.automaton.states.len();
+                self.automaton.states.push(State::default());
+                self.automaton.states[start_idx].goto.insert(start_rule, idx);
+                idx
+            }
+        };
+        if let Some(prev) = self.automaton.states[accept_idx].lookahead.insert(0, Action::Accept) {
+            return Err(Error::Error(format!("Ambiguous grammar, Not LR! On Token {:?} got {:?} and {:?}", Token::EOF, prev, Action::Accept)))
+        }
 
         Ok(self.automaton)
     }
@@ -140,7 +225,7 @@ impl AutomatonBuilder<'_> {
         let lr_ref = lr.state_map.get(&state_header).unwrap();
 
         let mut positions = Positions::new();
-        for (position, _) in &state_header {
+        for position in state_header.keys() {
             positions.add(position.clone())
         }
 
@@ -155,22 +240,29 @@ impl AutomatonBuilder<'_> {
         };
 
 
-        let mut state = State::default();
-        state.position = positions;
+        let mut state = State{position: positions, ..State::default()};
+
+        // transitions are baked in sorted order, so the numbering does not depend on the hash order
+        let mut next: Vec<_> = lr_ref.next.clone().into_iter().collect();
+        let mut reduce: Vec<_> = lr_ref.reduce.clone().into_iter().collect();
+        let mut goto: Vec<_> = lr_ref.goto.clone().into_iter().collect();
+        next.sort();
+        reduce.sort();
+        goto.sort();
 
         // Bake Shifts
-        for (token, next_impl) in lr_ref.next.clone() {
+        for (token, next_impl) in next {
             let next_idx = self.bake_state(lr, next_impl)?;
             let t = vecmap!(self, terminals, token);
             state.lookahead.insert(t, Action::Shift(next_idx));
         }
 
         // Bake Reduce
-        for (token, reductend_set) in lr_ref.reduce.clone() {
+        for (token, reductend_set) in reduce {
             let reductend = if reductend_set.len()==1 {
                 reductend_set.into_iter().nth(0).unwrap()
             } else {
-                return Err(Error::Error(format!("R/R error!")));
+                return Err(Error::Error("R/R error!".to_string()));
             };
             let reduction = self.make_reduction(reductend)?;
 
@@ -180,16 +272,11 @@ impl AutomatonBuilder<'_> {
             }
         }
 
-        // insert Token::EOF
-        if !state.lookahead.contains_key(&0) {
-            state.lookahead.insert(0, Action::Halt);
-        }
-
         // Bake goto
-        for (reductend, return_impl) in lr_ref.goto.clone() {
+        for (rule, return_impl) in goto {
             let return_idx = self.bake_state(lr, return_impl)?;
-            let reduction = self.make_reduction(reductend)?;
-            state.goto.insert(reduction, return_idx);
+            let nonterminal = self.make_nonterminal(rule);
+            state.goto.insert(nonterminal, return_idx);
         }
 
         // set state
@@ -197,8 +284,14 @@ impl AutomatonBuilder<'_> {
         Ok(state_idx)
     }
 
+    fn make_nonterminal(&mut self, rule: IdxRule) -> IdxNonterminal {
+        vecmap_get_or_insert!(self, nonterminals, rule, self.rules[rule].identifier.clone())
+    }
+
     fn make_reduction(&mut self, pos: ReductendPosition) -> Result<IdxReduction, Error>{
 
+        let nonterminal = self.make_nonterminal(pos.rule);
+
         let idx = vecmap_get_or_insert!(self, reductions, pos.clone(), {
 
             let rule_ref = self.rules.get(pos.rule).unwrap();
@@ -214,9 +307,11 @@ impl AutomatonBuilder<'_> {
                         let arg_type = match &component.handle {
                             parser::Component0::Regex(_)
                             | parser::Component0::Terminal(_)
+                            | parser::Component0::Insensitive(_)
+                            | parser::Component0::Synthetic(_)
                             | parser::Component0::Token => "&str".into(), // TODO advanced Types
                             parser::Component0::Rule(r) => {
-                                Position::rule_ref(self.rules, &r)?.export.clone().ok_or_else(||{println!("{:?}", r);todo!()})? // induce
+                                Position::rule_ref(self.rules, r)?.export.clone().ok_or_else(||{println!("{:?}", r);todo!()})? // induce
                             }
                         };
                         Some(Arg{identifier: identifier.clone(), arg_type})
@@ -227,12 +322,17 @@ impl AutomatonBuilder<'_> {
                 Some(ReductionTask{
                     code: code.clone(),
                     return_type: rule_ref.export.clone().unwrap(), // todo return induction
-                    args
+                    args,
+                    fallible: reductend.fallible
                 })
 
             } else {None};
 
-            let reduction = Reduction{task};
+            let reduction = Reduction{
+                nonterminal,
+                len: reductend.components.components.len(),
+                task
+            };
             Ok(reduction)
         }?);
         Ok(idx)
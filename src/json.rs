@@ -0,0 +1,154 @@
+// JSON dump of the grammar, the LR states and the baked automaton
+//
+// {
+//   "grammar": {
+//     "members": [{"name": str, "member_type": str}],
+//     "rules": [{"identifier": str, "export": str|null, "reductends": [
+//       {"components": [{"handle": Component, "var": str|null}], "code": str|null, "fallible": bool}
+//     ]}],
+//     "modes": [{"name": str, "tokens": [{"token": Component, "transition": "stay"|"pop"|{"push": str}}]}],
+//     "skip": [Component]|null,
+//     "indent": bool,
+//     "prelude": [str],                                      code in front of the generated parser
+//     "epilogue": [str],                                     code after the generated parser
+//     "context": str|null                                    type of the value passed to the actions
+//   },
+//   "lr": [[{"position": Position, "lookahead": [Token]}]],  one list of items per state, state 0 is the start state
+//   "automaton": {
+//     "export": str|null,                                    export type of the start rule
+//     "terminals": [Token],                                  index 0 is always "eof"
+//     "nonterminals": [str],
+//     "reductions": [{"nonterminal": int, "len": int, "task": null|{
+//       "code": str, "return_type": str, "args": [null|{"identifier": str, "arg_type": str}], "fallible": bool
+//     }}],
+//     "states": [{"position": [Position], "action": [Action], "goto": [int|null]}]
+//   }
+// }
+//
+// Component: {"rule": str} | {"terminal": str} | {"insensitive": str} | {"regex": str} | {"synthetic": Synthetic} | "token"
+// Token:     {"terminal": str} | {"insensitive": str} | {"regex": str} | "eof" | {"synthetic": Synthetic}
+// Synthetic: "indent" | "dedent" | "newline"
+// Position:  {"rule": int, "reductend": int, "component": int}, indices into grammar.rules
+// Action:    "error" | "accept" | {"shift": state} | {"reduce": reduction}
+//
+// every state row holds one action per terminal and one goto per nonterminal
+
+use crate::{lr::{self, Error, Position, Positions, Token}, automaton::{self, Action, Reduction}, parser};
+use serde::{Serialize, Deserialize};
+use std::{collections::BTreeSet, rc::Rc};
+
+#[derive(Serialize)]
+struct Item<'a> {
+    position: &'a Position,
+    lookahead: &'a BTreeSet<Token>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Row {
+    position: Positions,
+    action: Vec<Action>,
+    goto: Vec<Option<usize>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Automaton {
+    export: Option<Rc<str>>,
+    terminals: Vec<Token>,
+    nonterminals: Vec<Rc<str>>,
+    reductions: Vec<Reduction>,
+    states: Vec<Row>,
+}
+
+#[derive(Serialize)]
+struct Dump<'a> {
+    grammar: &'a parser::GAst,
+    lr: Vec<Vec<Item<'a>>>,
+    automaton: Automaton,
+}
+
+#[derive(Deserialize)]
+struct Load {
+    grammar: parser::GAst,
+    automaton: Automaton,
+}
+
+impl From<&automaton::Automaton> for Automaton {
+    fn from(value: &automaton::Automaton) -> Self {
+        let states = value.states.iter().map(|state| Row {
+            position: state.position.clone(),
+            action: (0..value.terminals.len()).map(|t| state.action(t)).collect(),
+            goto: (0..value.nonterminals.len()).map(|n| state.goto.get(&n).cloned()).collect(),
+        }).collect();
+        Self {
+            export: value.export.clone(),
+            terminals: value.terminals.clone(),
+            nonterminals: value.nonterminals.clone(),
+            reductions: value.reductions.clone(),
+            states,
+        }
+    }
+}
+
+impl TryFrom<Automaton> for automaton::Automaton {
+    type Error = Error;
+
+    fn try_from(value: Automaton) -> Result<Self, Error> {
+        let states_len = value.states.len();
+        let in_range = |target: usize, len: usize, what: &str| if target<len {Ok(())} else {
+            Err(Error::Error(format!("{} {} is out of range", what, target)))
+        };
+
+        if value.terminals.first() != Some(&Token::EOF) {
+            return Err(Error::Error("first terminal has to be eof".to_string()));
+        }
+        for reduction in &value.reductions {
+            in_range(reduction.nonterminal, value.nonterminals.len(), "Nonterminal")?;
+        }
+
+        let mut states = Vec::new();
+        for (i, row) in value.states.into_iter().enumerate() {
+            if row.action.len() != value.terminals.len() || row.goto.len() != value.nonterminals.len() {
+                return Err(Error::Error(format!("State {} does not match the number of terminals and nonterminals", i)));
+            }
+            let mut state = automaton::State{position: row.position, ..Default::default()};
+            for (t, action) in row.action.into_iter().enumerate() {
+                match action {
+                    Action::Error => continue,
+                    Action::Shift(s) => in_range(s, states_len, "State")?,
+                    Action::Reduce(r) => in_range(r, value.reductions.len(), "Reduction")?,
+                    Action::Accept => {}
+                }
+                state.lookahead.insert(t, action);
+            }
+            for (n, target) in row.goto.into_iter().enumerate() {
+                if let Some(s) = target {
+                    in_range(s, states_len, "State")?;
+                    state.goto.insert(n, s);
+                }
+            }
+            states.push(state);
+        }
+
+        Ok(Self {
+            export: value.export,
+            terminals: value.terminals,
+            nonterminals: value.nonterminals,
+            reductions: value.reductions,
+            states,
+        })
+    }
+}
+
+pub fn dump(ast: &parser::GAst, lr: &lr::LR, automaton: &automaton::Automaton) -> String {
+    let lr = lr.states().into_iter().map(|head| {
+        head.iter().map(|(position, lookahead)| Item{position, lookahead}).collect()
+    }).collect();
+    let dump = Dump{grammar: ast, lr, automaton: automaton.into()};
+    serde_json::to_string_pretty(&dump).unwrap()
+}
+
+// read the grammar and the automaton back from a dump, LR states are ignored
+pub fn load(source: &str) -> Result<(parser::GAst, automaton::Automaton), Error> {
+    let load: Load = serde_json::from_str(source).map_err(|e| Error::Error(format!("Invalid JSON: {}", e)))?;
+    Ok((load.grammar, load.automaton.try_into()?))
+}
@@ -1,57 +1,105 @@
-use crate::lr::Error;
+use crate::{lr::{self, Error}, parser};
 
-//TODO Replace Impl with Global Variables
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub enum Term{
     NGroup(Vec<char>),
     Group(Vec<char>),
     Pattern(Vec<Regexpr>),
-    PatternImpl(usize),
     Char(char),
-    Or(Vec<Regexpr>, Vec<Regexpr>),
-    OrImpl(usize, usize)
+    Or(Branches)
 }
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub enum Regexpr{
     Match(Term),
     Maybe(Term),
     Any(Term),
-    More(Term)
+    More(Term),
+    // at least min and at most max times
+    Repeat(Term, usize, Option<usize>)
 }
 
+pub type Branches = Vec<Vec<Regexpr>>;
+
+fn escape_char(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        c => c
+    }
+}
+
+// \n \t \r \0
+fn escape(s: &str) -> char {
+    escape_char(s.chars().nth(1).unwrap())
+}
+
+// chars of \d \w \s, the upper case classes are negated by the parser
+fn class(s: &str) -> Vec<char> {
+    match s.chars().nth(1).unwrap().to_ascii_lowercase() {
+        'd' => ('0'..='9').collect(),
+        'w' => ('a'..='z').chain('A'..='Z').chain('0'..='9').chain(['_']).collect(),
+        _ => vec![' ', '\t', '\n', '\r', '\x0b', '\x0c']
+    }
+}
+
+// {m} {m,} {m,n}
+fn count(s: &str) -> (usize, Option<usize>) {
+    let body = &s[1..s.len()-1];
+    match body.split_once(',') {
+        None => {
+            let n = body.parse().unwrap();
+            (n, Some(n))
+        }
+        Some((min, "")) => (min.parse().unwrap(), None),
+        Some((min, max)) => (min.parse().unwrap(), Some(max.parse().unwrap()))
+    }
+}
+
+// generated from regex.g, negated classes are spelled out as ranges of whole chars
 use logos::Logos;
 #[derive(Logos, Debug, PartialEq, PartialOrd)]
+#[allow(dead_code, clippy::upper_case_acronyms)]
 pub enum Token {
 	EOF,
-	#[token(r#"["#)]
+	#[token(r#"+"#)]
 	L1,
-	#[token(r#"?"#)]
+	#[token(r#"("#)]
 	L2,
-	#[token(r#"*"#)]
+	#[token(r#"?"#)]
 	L3,
-	#[token(r#"+"#)]
+	#[token(r#"["#)]
 	L4,
-	#[regex(r#"[^\[\]\(\)\.\\\+\*\|\?]"#)]
+	#[regex(r#"\\([\x00-\x2f\x31-\x43\x45-\x52\x54-\x56\x58-\x63\x65-\x6d\x6f-\x71\x75\x76\x78-\x7f]|[\u{80}-\u{10ffff}])"#)]
 	L5,
-	#[regex(r#"\\[\[\]\(\)\.\\\+\*\|\?]"#)]
+	#[token(r#"."#)]
 	L6,
-	#[token(r#"!"#)]
+	#[token(r#"[^"#)]
 	L7,
-	#[token(r#"("#)]
+	#[token(r#"|"#)]
 	L8,
-	#[token(r#"[^"#)]
+	#[regex(r#"\\[nrt0]"#)]
 	L9,
-	#[token(r#"-"#)]
+	#[regex(r#"\{[0-9]+(,[0-9]*)?\}"#)]
 	L10,
-	#[token(r#"]"#)]
+	#[token(r#"-"#)]
 	L11,
-	#[token(r#"|"#)]
+	#[regex(r#"[\x00-\x27\x2c\x2f-\x3e\x40-\x5a\x5e-\x7b\x7d-\x7f]|[\u{80}-\u{10ffff}]"#)]
 	L12,
-	#[token(r#")"#)]
+	#[token(r#"*"#)]
 	L13,
+	#[regex(r#"\\[DWS]"#)]
+	L14,
+	#[regex(r#"\\[dws]"#)]
+	L15,
+	#[token(r#")"#)]
+	L16,
+	#[token(r#"]"#)]
+	L17,
 }
 
-pub struct Parser<'a> {
+struct Parser<'a> {
 	parse_stack: Vec<Types<'a>>,
 	state_stack: Vec<usize>,
 	lexer: logos::Lexer<'a, Token>
@@ -70,171 +118,386 @@ macro_rules! pop{
 	}
 }
 impl<'a> Parser<'a> {
-	const ACTION: [ [isize; 14]; 69] = [
-		[0, 58, 0, 0, 0, 2, 3, 0, 16, 4, 0, 0, 0, 0],
-		[0, -1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 0, 0, 0],
-		[0, -2, -2, -2, -2, -2, -2, -2, -2, -2, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 5, 6, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, -1, -1, 0, 0, 0, -1, -1, 0, 0],
-		[0, 0, 0, 0, 0, -2, -2, 0, 0, 0, -2, -2, 0, 0],
-		[0, 0, 0, 0, 0, -4, -4, 0, 0, 0, 8, -4, 0, 0],
-		[0, 0, 0, 0, 0, 10, 9, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, -2, -2, 0, 0, 0, 0, -2, 0, 0],
-		[0, 0, 0, 0, 0, -1, -1, 0, 0, 0, 0, -1, 0, 0],
-		[0, 0, 0, 0, 0, -3, -3, 0, 0, 0, 0, -3, 0, 0],
-		[0, 0, 0, 0, 0, 5, 6, 0, 0, 0, 0, 13, 0, 0],
-		[0, -5, -5, -5, -5, -5, -5, -5, -5, -5, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, -6, -6, 0, 0, 0, 0, -6, 0, 0],
-		[0, 0, 0, 0, 0, -7, -7, 0, 0, 0, 0, -7, 0, 0],
-		[0, 21, 0, 0, 0, 24, 25, 0, 17, 18, 0, 0, 0, 0],
-		[0, 21, 0, 0, 0, 24, 25, 0, 17, 18, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 5, 6, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 5, 6, 0, 0, 0, 0, 20, 0, 0],
-		[0, -5, -5, -5, -5, -5, -5, 0, -5, -5, 0, 0, -5, -5],
-		[0, 0, 0, 0, 0, 5, 6, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 5, 6, 0, 0, 0, 0, 23, 0, 0],
-		[0, -8, -8, -8, -8, -8, -8, 0, -8, -8, 0, 0, -8, -8],
-		[0, -1, -1, -1, -1, -1, -1, 0, -1, -1, 0, 0, -1, -1],
-		[0, -2, -2, -2, -2, -2, -2, 0, -2, -2, 0, 0, -2, -2],
-		[0, -12, 27, 28, 29, -12, -12, 0, -12, -12, 0, 0, -12, -12],
-		[0, -9, 0, 0, 0, -9, -9, 0, -9, -9, 0, 0, -9, -9],
-		[0, -10, 0, 0, 0, -10, -10, 0, -10, -10, 0, 0, -10, -10],
-		[0, -11, 0, 0, 0, -11, -11, 0, -11, -11, 0, 0, -11, -11],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 31],
-		[0, -14, -14, -14, -14, -14, -14, 0, -14, -14, 0, 0, -14, -14],
-		[0, 21, 0, 0, 0, 24, 25, 0, 17, 18, 0, 0, 33, -15],
-		[0, 44, 0, 0, 0, 42, 43, 0, 37, 34, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 5, 6, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 5, 6, 0, 0, 0, 0, 36, 0, 0],
-		[0, -5, -5, -5, -5, -5, -5, 0, -5, -5, 0, 0, 0, -5],
-		[0, 21, 0, 0, 0, 24, 25, 0, 17, 18, 0, 0, 0, 0],
-		[0, -16, 0, 0, 0, -16, -16, 0, -16, -16, 0, 0, -16, -16],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 40],
-		[0, -14, -14, -14, -14, -14, -14, 0, -14, -14, 0, 0, 0, -14],
-		[0, -13, -13, -13, -13, -13, -13, 0, -13, -13, 0, 0, -13, -13],
-		[0, -1, -1, -1, -1, -1, -1, 0, -1, -1, 0, 0, 0, -1],
-		[0, -2, -2, -2, -2, -2, -2, 0, -2, -2, 0, 0, 0, -2],
-		[0, 0, 0, 0, 0, 5, 6, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 5, 6, 0, 0, 0, 0, 46, 0, 0],
-		[0, -8, -8, -8, -8, -8, -8, 0, -8, -8, 0, 0, 0, -8],
-		[0, -16, 0, 0, 0, -16, -16, 0, -16, -16, 0, 0, 0, -16],
-		[0, 44, 0, 0, 0, 42, 43, 0, 37, 34, 0, 0, 0, -18],
-		[0, -13, -13, -13, -13, -13, -13, 0, -13, -13, 0, 0, 0, -13],
-		[0, -17, 0, 0, 0, -17, -17, 0, -17, -17, 0, 0, 0, -17],
-		[0, -12, 53, 52, 54, -12, -12, 0, -12, -12, 0, 0, 0, -12],
-		[0, -10, 0, 0, 0, -10, -10, 0, -10, -10, 0, 0, 0, -10],
-		[0, -9, 0, 0, 0, -9, -9, 0, -9, -9, 0, 0, 0, -9],
-		[0, -11, 0, 0, 0, -11, -11, 0, -11, -11, 0, 0, 0, -11],
-		[0, -17, 0, 0, 0, -17, -17, 0, -17, -17, 0, 0, -17, -17],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 57],
-		[0, -14, -14, -14, -14, -14, -14, -14, -14, -14, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 5, 6, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 5, 6, 0, 0, 0, 0, 60, 0, 0],
-		[0, -8, -8, -8, -8, -8, -8, -8, -8, -8, 0, 0, 0, 0],
-		[0, -16, 0, 0, 0, -16, -16, -16, -16, -16, 0, 0, 0, 0],
-		[0, -12, 63, 65, 64, -12, -12, -12, -12, -12, 0, 0, 0, 0],
-		[0, -9, 0, 0, 0, -9, -9, -9, -9, -9, 0, 0, 0, 0],
-		[0, -11, 0, 0, 0, -11, -11, -11, -11, -11, 0, 0, 0, 0],
-		[0, -10, 0, 0, 0, -10, -10, -10, -10, -10, 0, 0, 0, 0],
-		[0, 58, 0, 0, 0, 2, 3, 67, 16, 4, 0, 0, 0, 0],
-		[-19, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, -13, -13, -13, -13, -13, -13, -13, -13, -13, 0, 0, 0, 0],
-		[0, -17, 0, 0, 0, -17, -17, -17, -17, -17, 0, 0, 0, 0],
+	const ACTION: [ [isize; 18]; 82] = [
+		[0, 0, 41, 0, 32, 37, 36, 4, 0, 35, 0, 38, 3, 0, 39, 40, 0, 0], 
+		[-1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 0], 
+		[0, 10, 12, 11, 0, 13, 7, 0, 5, 14, 0, 17, 6, 9, 0, 15, 8, 0], 
+		[0, -2, -2, -2, 0, -2, -2, 0, -2, -2, 0, -2, -2, -2, 0, -2, -2, -2], 
+		[0, -1, -1, -1, 0, -1, -1, 0, -1, -1, 0, -1, -1, -1, 0, -1, -1, -1], 
+		[0, -3, -3, -3, 0, -3, -3, 0, -3, -3, 0, -3, -3, -3, 0, -3, -3, -3], 
+		[0, -4, -4, -4, 0, -4, -4, 0, -4, -4, 0, -4, -4, -4, 0, -4, -4, -4], 
+		[0, -5, -5, -5, 0, -5, -5, 0, -5, -5, 0, -5, -5, -5, 0, -5, -5, -5], 
+		[0, -6, -6, -6, 0, -6, -6, 0, -6, -6, 0, -6, -6, -6, 0, -6, -6, -6], 
+		[0, -7, -7, -7, 0, -7, -7, 0, -7, -7, 0, -7, -7, -7, 0, -7, -7, -7], 
+		[0, -8, -8, -8, 0, -8, -8, 0, -8, -8, 0, -8, -8, -8, 0, -8, -8, -8], 
+		[0, -9, -9, -9, 0, -9, -9, 0, -9, -9, 0, -9, -9, -9, 0, -9, -9, -9], 
+		[0, -10, -10, -10, 0, -10, -10, 0, -10, -10, 0, -10, -10, -10, 0, -10, -10, -10], 
+		[0, -12, -12, -12, 0, -12, -12, 0, -12, -12, 0, 16, -12, -12, 0, -12, -12, -12], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, -11], 
+		[0, 10, 12, 11, 0, 13, 7, 0, 5, 14, 0, 0, 6, 9, 0, 15, 8, -13], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, -14], 
+		[0, 10, 12, 11, 0, 13, 7, 0, 5, 14, 0, 0, 6, 9, 0, 15, 8, -15], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, -16], 
+		[0, -17, -17, -17, 0, -17, -17, 0, -17, -17, 0, -17, -17, -17, 0, -17, -17, -17], 
+		[0, -21, -21, -21, 0, -21, -21, 0, -21, -21, 0, 23, -21, -21, 0, -21, -21, -21], 
+		[0, 10, 12, 11, 0, 13, 7, 0, 5, 14, 0, 0, 6, 9, 0, 0, 8, -18], 
+		[0, -20, -20, -20, 0, -20, -20, 0, -20, -20, 0, 25, -20, -20, 0, -20, -20, -20], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, -19], 
+		[0, -22, -22, -22, 0, -22, -22, 0, -22, -22, 0, 0, -22, -22, 0, -22, -22, -22], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, -23], 
+		[0, -24, -24, -24, 0, -24, -24, 0, -24, -24, 0, 0, -24, -24, 0, -24, -24, -24], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 30], 
+		[-25, -25, -25, -25, -25, -25, -25, -25, -25, -25, -25, -25, -25, -25, -25, -25, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, -26], 
+		[0, 10, 12, 11, 0, 13, 7, 0, 5, 14, 0, 17, 6, 9, 0, 15, 8, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 34], 
+		[-27, -27, -27, -27, -27, -27, -27, -27, -27, -27, -27, -27, -27, -27, -27, -27, 0, 0], 
+		[-10, -10, -10, -10, -10, -10, -10, -10, -10, -10, -10, -10, -10, -10, -10, -10, 0, 0], 
+		[-28, -28, -28, -28, -28, -28, -28, -28, -28, -28, -28, -28, -28, -28, -28, -28, 0, 0], 
+		[-9, -9, -9, -9, -9, -9, -9, -9, -9, -9, -9, -9, -9, -9, -9, -9, 0, 0], 
+		[-29, -29, -29, -29, -29, -29, -29, -29, -29, -29, -29, -29, -29, -29, -29, -29, 0, 0], 
+		[-30, -30, -30, -30, -30, -30, -30, -30, -30, -30, -30, -30, -30, -30, -30, -30, 0, 0], 
+		[-31, -31, -31, -31, -31, -31, -31, -31, -31, -31, -31, -31, -31, -31, -31, -31, 0, 0], 
+		[0, 0, 53, 0, 47, 54, 50, 42, 0, 45, 0, 52, 55, 0, 51, 46, 0, 0], 
+		[0, 10, 12, 11, 0, 13, 7, 0, 5, 14, 0, 17, 6, 9, 0, 15, 8, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 44], 
+		[0, -25, -25, -25, -25, -25, -25, -25, -25, -25, -25, -25, -25, -25, -25, -25, -25, 0], 
+		[0, -10, -10, -10, -10, -10, -10, -10, -10, -10, -10, -10, -10, -10, -10, -10, -10, 0], 
+		[0, -31, -31, -31, -31, -31, -31, -31, -31, -31, -31, -31, -31, -31, -31, -31, -31, 0], 
+		[0, 10, 12, 11, 0, 13, 7, 0, 5, 14, 0, 17, 6, 9, 0, 15, 8, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 49], 
+		[0, -27, -27, -27, -27, -27, -27, -27, -27, -27, -27, -27, -27, -27, -27, -27, -27, 0], 
+		[0, -28, -28, -28, -28, -28, -28, -28, -28, -28, -28, -28, -28, -28, -28, -28, -28, 0], 
+		[0, -30, -30, -30, -30, -30, -30, -30, -30, -30, -30, -30, -30, -30, -30, -30, -30, 0], 
+		[0, -29, -29, -29, -29, -29, -29, -29, -29, -29, -29, -29, -29, -29, -29, -29, -29, 0], 
+		[0, 0, 53, 0, 47, 54, 50, 42, 0, 45, 0, 52, 55, 0, 51, 46, 0, 0], 
+		[0, -9, -9, -9, -9, -9, -9, -9, -9, -9, -9, -9, -9, -9, -9, -9, -9, 0], 
+		[0, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 58, 0, 0, 0, 0, 0, 0, 0, 57, 0], 
+		[0, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, 0], 
+		[0, 0, 53, 0, 47, 54, 50, 42, 0, 45, 0, 52, 55, 0, 51, 46, 0, 0], 
+		[0, 0, -33, 0, -33, -33, -33, -33, -33, -33, 0, -33, -33, 0, -33, -33, -33, 0], 
+		[0, 0, 53, 0, 47, 54, 50, 42, -34, 45, 0, 52, 55, 0, 51, 46, -34, 0], 
+		[0, 62, -39, 63, -39, -39, -39, -39, -39, -39, 64, -39, -39, 65, -39, -39, -39, 0], 
+		[0, 0, -35, 0, -35, -35, -35, -35, -35, -35, 0, -35, -35, 0, -35, -35, -35, 0], 
+		[0, 0, -36, 0, -36, -36, -36, -36, -36, -36, 0, -36, -36, 0, -36, -36, -36, 0], 
+		[0, 0, -37, 0, -37, -37, -37, -37, -37, -37, 0, -37, -37, 0, -37, -37, -37, 0], 
+		[0, 0, -38, 0, -38, -38, -38, -38, -38, -38, 0, -38, -38, 0, -38, -38, -38, 0], 
+		[0, 0, -40, 0, -40, -40, -40, -40, -40, -40, 0, -40, -40, 0, -40, -40, -40, 0], 
+		[0, -41, -41, -41, -41, -41, -41, -41, -41, -41, -41, -41, -41, -41, -41, -41, -41, 0], 
+		[0, 0, 53, 0, 47, 54, 50, 42, -42, 45, 0, 52, 55, 0, 51, 46, -42, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 58, 0, 0, 0, 0, 0, 0, 0, 70, 0], 
+		[-32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, -32, 0, 0], 
+		[-33, 0, -33, 0, -33, -33, -33, -33, -33, -33, 0, -33, -33, 0, -33, -33, 0, 0], 
+		[-41, -41, -41, -41, -41, -41, -41, -41, -41, -41, -41, -41, -41, -41, -41, -41, 0, 0], 
+		[-42, 0, 41, 0, 32, 37, 36, 4, -42, 35, 0, 38, 3, 0, 39, 40, 0, 0], 
+		[-40, 0, -40, 0, -40, -40, -40, -40, -40, -40, 0, -40, -40, 0, -40, -40, 0, 0], 
+		[-39, 79, -39, 77, -39, -39, -39, -39, -39, -39, 78, -39, -39, 76, -39, -39, 0, 0], 
+		[-38, 0, -38, 0, -38, -38, -38, -38, -38, -38, 0, -38, -38, 0, -38, -38, 0, 0], 
+		[-36, 0, -36, 0, -36, -36, -36, -36, -36, -36, 0, -36, -36, 0, -36, -36, 0, 0], 
+		[-37, 0, -37, 0, -37, -37, -37, -37, -37, -37, 0, -37, -37, 0, -37, -37, 0, 0], 
+		[-35, 0, -35, 0, -35, -35, -35, -35, -35, -35, 0, -35, -35, 0, -35, -35, 0, 0], 
+		[-43, 0, 0, 0, 0, 0, 0, 0, 81, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 41, 0, 32, 37, 36, 4, 0, 35, 0, 38, 3, 0, 39, 40, 0, 0], 
+		[-34, 0, 41, 0, 32, 37, 36, 4, -34, 35, 0, 38, 3, 0, 39, 40, 0, 0], 
+		[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
 	];
 
-	const GOTO: [ [usize; 19]; 69] = [
-		[67, 67, 0, 0, 61, 0, 0, 61, 60, 60, 60, 60, 61, 61, 0, 65, 65, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[6, 6, 14, 14, 0, 11, 11, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[10, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[6, 6, 13, 13, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[40, 40, 0, 0, 25, 0, 0, 25, 37, 37, 37, 37, 25, 25, 55, 31, 31, 55, 0],
-		[40, 40, 0, 0, 25, 0, 0, 25, 37, 37, 37, 37, 25, 25, 29, 31, 31, 29, 0],
-		[6, 6, 14, 14, 0, 18, 18, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[6, 6, 13, 13, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[6, 6, 14, 14, 0, 21, 21, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[6, 6, 13, 13, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[40, 40, 0, 0, 25, 0, 0, 25, 54, 54, 54, 54, 25, 25, 0, 0, 0, 0, 0],
-		[48, 48, 0, 0, 50, 0, 0, 50, 46, 46, 46, 46, 50, 50, 0, 47, 47, 0, 0],
-		[6, 6, 14, 14, 0, 34, 34, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[6, 6, 13, 13, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[40, 40, 0, 0, 25, 0, 0, 25, 37, 37, 37, 37, 25, 25, 38, 31, 31, 38, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[6, 6, 14, 14, 0, 44, 44, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[6, 6, 13, 13, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[48, 48, 0, 0, 50, 0, 0, 50, 49, 49, 49, 49, 50, 50, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[6, 6, 14, 14, 0, 58, 58, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[6, 6, 13, 13, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[67, 67, 0, 0, 61, 0, 0, 61, 68, 68, 68, 68, 61, 61, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+	const GOTO: [ [usize; 12]; 82] = [
+		[70, 0, 0, 0, 0, 0, 0, 73, 71, 69, 78, 81], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[19, 20, 25, 26, 27, 29, 17, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[19, 20, 25, 26, 0, 16, 17, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[19, 20, 18, 24, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[19, 22, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[19, 20, 25, 26, 31, 29, 17, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[65, 0, 0, 0, 0, 0, 0, 59, 66, 57, 67, 0], 
+		[19, 20, 25, 26, 41, 29, 17, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[19, 20, 25, 26, 46, 29, 17, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[65, 0, 0, 0, 0, 0, 0, 59, 66, 57, 54, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[65, 0, 0, 0, 0, 0, 0, 59, 58, 57, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[65, 0, 0, 0, 0, 0, 0, 59, 0, 64, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[65, 0, 0, 0, 0, 0, 0, 59, 0, 64, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[70, 0, 0, 0, 0, 0, 0, 73, 0, 72, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
+		[70, 0, 0, 0, 0, 0, 0, 73, 80, 69, 0, 0], 
+		[70, 0, 0, 0, 0, 0, 0, 73, 0, 72, 0, 0], 
+		[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 
 	];
 
-	fn reduction0(mut s: &str) -> char {s.chars().next().unwrap()}
-	fn reduction1(mut s: &str) -> char {s.chars().nth(1).unwrap()}
-	fn reduction2(mut a: char, mut b: char) -> Vec<char> {(a..=b).collect()}
-	fn reduction3(mut c: char) -> Vec<char> {vec![c]}
-	fn reduction4(mut s: Vec<char>) -> Term {Term::NGroup(s)}
-	fn reduction5(mut stack: Vec<char>, mut s: Vec<char>) -> Vec<char> {stack.extend(s); stack}
-	fn reduction6(mut s: Vec<char>) -> Vec<char> {s}
-	fn reduction7(mut s: Vec<char>) -> Term {Term::Group(s)}
-	fn reduction8(mut t: Term) -> Regexpr {Regexpr::Maybe(t)       }
-	fn reduction9(mut t: Term) -> Regexpr {Regexpr::Any(t)         }
-	fn reduction10(mut t: Term) -> Regexpr {Regexpr::More(t.clone())}
-	fn reduction11(mut t: Term) -> Regexpr {Regexpr::Match(t)       }
-	fn reduction12(mut s: char) -> Term {Term::Char(s)}
-	fn reduction13(mut p: Term) -> Term {p}
-	fn reduction14(mut r: Vec<Regexpr>) -> Term {Term::Pattern(r)}
-	fn reduction15(mut e: Regexpr) -> Vec<Regexpr> {vec![e]}
-	fn reduction16(mut stack: Vec<Regexpr>, mut e: Regexpr) -> Vec<Regexpr> {stack.push(e); stack}
-	fn reduction17(mut a: Vec<Regexpr>, mut b: Vec<Regexpr>) -> Term {Term::Or(a,b)}
-	fn reduction18(mut r: Vec<Regexpr>) -> Vec<Regexpr> {r}
-
-    pub fn parse(lex: logos::Lexer<'a, Token>) -> Vec<Regexpr> {
+	const REDUCTIONS: [(usize, usize); 43] = [
+		(0, 1),
+		(1, 1),
+		(1, 1),
+		(1, 1),
+		(1, 1),
+		(1, 1),
+		(1, 1),
+		(1, 1),
+		(0, 1),
+		(0, 1),
+		(2, 2),
+		(3, 1),
+		(4, 1),
+		(4, 2),
+		(5, 1),
+		(5, 2),
+		(1, 1),
+		(2, 2),
+		(2, 4),
+		(3, 3),
+		(3, 1),
+		(6, 2),
+		(5, 1),
+		(6, 1),
+		(7, 3),
+		(4, 1),
+		(7, 3),
+		(7, 1),
+		(7, 1),
+		(7, 1),
+		(7, 1),
+		(7, 3),
+		(8, 1),
+		(10, 3),
+		(9, 2),
+		(9, 2),
+		(9, 2),
+		(9, 2),
+		(9, 1),
+		(8, 2),
+		(7, 1),
+		(10, 1),
+		(11, 1),
+	];
+
+	const TOKEN_NAMES: [&'static str; 18] = [
+		"EOF",
+		"\"+\"",
+		"\"(\"",
+		"\"?\"",
+		"\"[\"",
+		"r\"\\\\[^nrt0dwsDWS]\"",
+		"\".\"",
+		"\"[^\"",
+		"\"|\"",
+		"r\"\\\\[nrt0]\"",
+		"r\"\\{[0-9]+(,[0-9]*)?\\}\"",
+		"\"-\"",
+		"r\"[^\\[\\]\\(\\)\\.\\\\\\+\\*\\|\\?\\-]\"",
+		"\"*\"",
+		"r\"\\\\[DWS]\"",
+		"r\"\\\\[dws]\"",
+		"\")\"",
+		"\"]\"",
+	];
+
+	const EXPECTED: [&'static [usize]; 82] = [
+		&[2, 4, 5, 6, 7, 9, 11, 12, 14, 15],
+		&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16, 17],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16, 17],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16, 17],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16, 17],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16, 17],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16, 17],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16, 17],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16, 17],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16, 17],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16, 17],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16, 17],
+		&[17],
+		&[1, 2, 3, 5, 6, 8, 9, 12, 13, 15, 16, 17],
+		&[17],
+		&[1, 2, 3, 5, 6, 8, 9, 12, 13, 15, 16, 17],
+		&[17],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16, 17],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16, 17],
+		&[1, 2, 3, 5, 6, 8, 9, 12, 13, 16, 17],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16, 17],
+		&[17],
+		&[1, 2, 3, 5, 6, 8, 9, 12, 13, 15, 16, 17],
+		&[17],
+		&[1, 2, 3, 5, 6, 8, 9, 12, 13, 15, 16, 17],
+		&[17],
+		&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+		&[17],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16],
+		&[17],
+		&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+		&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+		&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+		&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+		&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+		&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+		&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+		&[2, 4, 5, 6, 7, 9, 11, 12, 14, 15],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16],
+		&[17],
+		&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+		&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+		&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+		&[1, 2, 3, 5, 6, 8, 9, 11, 12, 13, 15, 16],
+		&[17],
+		&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+		&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+		&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+		&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+		&[2, 4, 5, 6, 7, 9, 11, 12, 14, 15],
+		&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+		&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+		&[8, 16],
+		&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+		&[2, 4, 5, 6, 7, 9, 11, 12, 14, 15],
+		&[2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15, 16],
+		&[2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15, 16],
+		&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+		&[2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15, 16],
+		&[2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15, 16],
+		&[2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15, 16],
+		&[2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15, 16],
+		&[2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15, 16],
+		&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+		&[2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15, 16],
+		&[8, 16],
+		&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+		&[0, 2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15],
+		&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+		&[0, 2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15],
+		&[0, 2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15],
+		&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+		&[0, 2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15],
+		&[0, 2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15],
+		&[0, 2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15],
+		&[0, 2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15],
+		&[0, 8],
+		&[2, 4, 5, 6, 7, 9, 11, 12, 14, 15],
+		&[0, 2, 4, 5, 6, 7, 8, 9, 11, 12, 14, 15],
+		&[0],
+	];
+
+	fn location(&self) -> (usize, usize) {
+		let before = &self.lexer.source()[..self.lexer.span().start];
+		let line = before.matches('\n').count() + 1;
+		let column = before.len() - before.rfind('\n').map_or(0, |i| i+1) + 1;
+		(line, column)
+	}
+
+	fn error(&self, state: usize, token: usize) -> Error {
+		let (line, column) = self.location();
+		let expected: Vec<_> = Self::EXPECTED[state].iter().map(|t| Self::TOKEN_NAMES[*t]).collect();
+		Error::Error(format!("{}:{}: Unexpected Token {} ({:?}), expected one of {}", line, column, Self::TOKEN_NAMES[token], self.lexer.slice(), expected.join(", ")))
+	}
+
+	fn reduction0(s: &str) -> char {s.chars().next().unwrap()} 
+	fn reduction1() -> char {'|'} 
+	fn reduction2() -> char {'.'} 
+	fn reduction3() -> char {')'} 
+	fn reduction4() -> char {'*'} 
+	fn reduction5() -> char {'+'} 
+	fn reduction6() -> char {'?'} 
+	fn reduction7() -> char {'('} 
+	fn reduction8(s: &str) -> char {s.chars().nth(1).unwrap()} 
+	fn reduction9(s: &str) -> char {escape(s)} 
+	fn reduction10(s: &str) -> Vec<char> {let mut s = class(s); s.push('-'); s} 
+	fn reduction11(s: &str) -> Vec<char> {class(s)} 
+	fn reduction12() -> Vec<char> {vec!['-']} 
+	fn reduction13(mut s: Vec<char>) -> Vec<char> {s.push('-'); s} 
+	fn reduction14(s: Vec<char>) -> Vec<char> {s} 
+	fn reduction15(mut stack: Vec<char>, s: Vec<char>) -> Vec<char> {stack.extend(s); stack} 
+	fn reduction16(c: char) -> char {c} 
+	fn reduction17(c: char) -> Vec<char> {vec![c, '-']} 
+	fn reduction18(a: char, b: char) -> Vec<char> {let mut s: Vec<char> = (a..=b).collect(); s.push('-'); s} 
+	fn reduction19(a: char, b: char) -> Vec<char> {(a..=b).collect()} 
+	fn reduction20(c: char) -> Vec<char> {vec![c]} 
+	fn reduction21(mut stack: Vec<char>, s: Vec<char>) -> Vec<char> {stack.extend(s); stack} 
+	fn reduction22(s: Vec<char>) -> Vec<char> {s} 
+	fn reduction23(s: Vec<char>) -> Vec<char> {s} 
+	fn reduction24(s: Vec<char>) -> Term {Term::NGroup(s)} 
+	fn reduction25(s: Vec<char>) -> Vec<char> {s} 
+	fn reduction26(s: Vec<char>) -> Term {Term::Group(s)} 
+	fn reduction27(_s: &str) -> Term {Term::NGroup(vec!['\n'])} 
+	fn reduction28(_s: &str) -> Term {Term::Char('-')} 
+	fn reduction29(s: &str) -> Term {Term::NGroup(class(s))} 
+	fn reduction30(s: &str) -> Term {Term::Group(class(s))} 
+	fn reduction31(mut a: Branches) -> Term {if a.len()==1 {Term::Pattern(a.pop().unwrap())} else {Term::Or(a)}} 
+	fn reduction32(e: Regexpr) -> Vec<Regexpr> {vec![e]} 
+	fn reduction33(mut stack: Branches, r: Vec<Regexpr>) -> Branches {stack.push(r); stack} 
+	fn reduction34(t: Term) -> Regexpr {Regexpr::More(t)        } 
+	fn reduction35(t: Term) -> Regexpr {Regexpr::Maybe(t)       } 
+	fn reduction36(t: Term, c: &str) -> Regexpr {let (min, max) = count(c); Regexpr::Repeat(t, min, max)} 
+	fn reduction37(t: Term) -> Regexpr {Regexpr::Any(t)         } 
+	fn reduction38(t: Term) -> Regexpr {Regexpr::Match(t)       } 
+	fn reduction39(mut stack: Vec<Regexpr>, e: Regexpr) -> Vec<Regexpr> {stack.push(e); stack} 
+	fn reduction40(s: char) -> Term {Term::Char(s)} 
+	fn reduction41(r: Vec<Regexpr>) -> Branches {vec![r]} 
+	fn reduction42(mut a: Branches) -> Vec<Regexpr> {if a.len()==1 {a.pop().unwrap()} else {vec![Regexpr::Match(Term::Or(a))]}} 
+
+    fn parse(lex: logos::Lexer<'a, Token>) -> Result<Vec<Regexpr>, Error> {
         let mut parser = Self{
             parse_stack: vec![],
             state_stack: vec![0],
@@ -243,430 +506,1283 @@ impl<'a> Parser<'a> {
 
         let mut token = match parser.lexer.next() {
             Some(Ok(t)) => t as usize,
-            Some(Err(e)) => panic!("{:?}", e),
+            Some(Err(e)) => return Err(Error::Error(format!("{:?}", e))),
             None => 0
         };
 
-        while parser.state_stack.len()>0 {
+        loop {
             let state = *parser.state_stack.last().unwrap();
-            println!("stack: {:?}", parser.parse_stack);
-            println!("stack: {:?}", parser.state_stack);
-            println!("got: {}:{}", state, token.clone() as usize);
             let task = Self::ACTION[state][token];
-            println!("task: {}", task);
             match task {
-                0 => break,
+                0 => return Err(parser.error(state, token)),
+                1 => break,
 			-1 => {
 				let a0 = pop!(parser, T1);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T2, Self::reduction0(a0));
+ 				push!(parser, T2, Self::reduction0(a0));
 			}
 			-2 => {
-				let a0 = pop!(parser, T1);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T2, Self::reduction1(a0));
+				let _ = parser.parse_stack.pop();
+				push!(parser, T2, Self::reduction1());
 			}
 			-3 => {
-				let a2 = pop!(parser, T2);
- 				let _ = parser.state_stack.pop();
 				let _ = parser.parse_stack.pop();
-				let _ = parser.state_stack.pop();
-				let a0 = pop!(parser, T2);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T3, Self::reduction2(a0, a2));
+				push!(parser, T2, Self::reduction2());
 			}
 			-4 => {
-				let a0 = pop!(parser, T2);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T3, Self::reduction3(a0));
+				let _ = parser.parse_stack.pop();
+				push!(parser, T2, Self::reduction3());
 			}
 			-5 => {
 				let _ = parser.parse_stack.pop();
-				let _ = parser.state_stack.pop();
-				let a1 = pop!(parser, T3);
- 				let _ = parser.state_stack.pop();
-				let _ = parser.parse_stack.pop();
-				let _ = parser.state_stack.pop();
-				push!(parser, T4, Self::reduction4(a1));
+				push!(parser, T2, Self::reduction4());
 			}
 			-6 => {
-				let a1 = pop!(parser, T3);
- 				let _ = parser.state_stack.pop();
-				let a0 = pop!(parser, T3);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T3, Self::reduction5(a0, a1));
+				let _ = parser.parse_stack.pop();
+				push!(parser, T2, Self::reduction5());
 			}
 			-7 => {
-				let a0 = pop!(parser, T3);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T3, Self::reduction6(a0));
+				let _ = parser.parse_stack.pop();
+				push!(parser, T2, Self::reduction6());
 			}
 			-8 => {
 				let _ = parser.parse_stack.pop();
-				let _ = parser.state_stack.pop();
-				let a1 = pop!(parser, T3);
- 				let _ = parser.state_stack.pop();
-				let _ = parser.parse_stack.pop();
-				let _ = parser.state_stack.pop();
-				push!(parser, T4, Self::reduction7(a1));
+				push!(parser, T2, Self::reduction7());
 			}
 			-9 => {
-				let _ = parser.parse_stack.pop();
-				let _ = parser.state_stack.pop();
-				let a0 = pop!(parser, T4);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T5, Self::reduction8(a0));
+				let a0 = pop!(parser, T1);
+ 				push!(parser, T2, Self::reduction8(a0));
 			}
 			-10 => {
-				let _ = parser.parse_stack.pop();
-				let _ = parser.state_stack.pop();
-				let a0 = pop!(parser, T4);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T5, Self::reduction9(a0));
+				let a0 = pop!(parser, T1);
+ 				push!(parser, T2, Self::reduction9(a0));
 			}
 			-11 => {
 				let _ = parser.parse_stack.pop();
-				let _ = parser.state_stack.pop();
-				let a0 = pop!(parser, T4);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T5, Self::reduction10(a0));
+				let a0 = pop!(parser, T1);
+ 				push!(parser, T3, Self::reduction10(a0));
 			}
 			-12 => {
-				let a0 = pop!(parser, T4);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T5, Self::reduction11(a0));
+				let a0 = pop!(parser, T1);
+ 				push!(parser, T3, Self::reduction11(a0));
 			}
 			-13 => {
-				let a0 = pop!(parser, T2);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T4, Self::reduction12(a0));
+				let _ = parser.parse_stack.pop();
+				push!(parser, T3, Self::reduction12());
 			}
 			-14 => {
-				let _ = parser.parse_stack.pop();
-				let _ = parser.state_stack.pop();
-				let a1 = pop!(parser, T4);
- 				let _ = parser.state_stack.pop();
-				let _ = parser.parse_stack.pop();
-				let _ = parser.state_stack.pop();
-				push!(parser, T4, Self::reduction13(a1));
+				let a1 = pop!(parser, T3);
+ 				let _ = parser.parse_stack.pop();
+				push!(parser, T3, Self::reduction13(a1));
 			}
 			-15 => {
-				let a0 = pop!(parser, T6);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T4, Self::reduction14(a0));
+				let a0 = pop!(parser, T3);
+ 				push!(parser, T3, Self::reduction14(a0));
 			}
 			-16 => {
-				let a0 = pop!(parser, T5);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T6, Self::reduction15(a0));
+				let a1 = pop!(parser, T3);
+ 				let a0 = pop!(parser, T3);
+ 				push!(parser, T3, Self::reduction15(a0, a1));
 			}
 			-17 => {
-				let a1 = pop!(parser, T5);
- 				let _ = parser.state_stack.pop();
-				let a0 = pop!(parser, T6);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T6, Self::reduction16(a0, a1));
+				let a0 = pop!(parser, T2);
+ 				push!(parser, T2, Self::reduction16(a0));
 			}
 			-18 => {
-				let a2 = pop!(parser, T6);
- 				let _ = parser.state_stack.pop();
 				let _ = parser.parse_stack.pop();
-				let _ = parser.state_stack.pop();
-				let a0 = pop!(parser, T6);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T4, Self::reduction17(a0, a2));
+				let a0 = pop!(parser, T2);
+ 				push!(parser, T3, Self::reduction17(a0));
 			}
 			-19 => {
 				let _ = parser.parse_stack.pop();
-				let _ = parser.state_stack.pop();
+				let a2 = pop!(parser, T2);
+ 				let _ = parser.parse_stack.pop();
+				let a0 = pop!(parser, T2);
+ 				push!(parser, T3, Self::reduction18(a0, a2));
+			}
+			-20 => {
+				let a2 = pop!(parser, T2);
+ 				let _ = parser.parse_stack.pop();
+				let a0 = pop!(parser, T2);
+ 				push!(parser, T3, Self::reduction19(a0, a2));
+			}
+			-21 => {
+				let a0 = pop!(parser, T2);
+ 				push!(parser, T3, Self::reduction20(a0));
+			}
+			-22 => {
+				let a1 = pop!(parser, T3);
+ 				let a0 = pop!(parser, T3);
+ 				push!(parser, T3, Self::reduction21(a0, a1));
+			}
+			-23 => {
+				let a0 = pop!(parser, T3);
+ 				push!(parser, T3, Self::reduction22(a0));
+			}
+			-24 => {
+				let a0 = pop!(parser, T3);
+ 				push!(parser, T3, Self::reduction23(a0));
+			}
+			-25 => {
+				let _ = parser.parse_stack.pop();
+				let a1 = pop!(parser, T3);
+ 				let _ = parser.parse_stack.pop();
+				push!(parser, T4, Self::reduction24(a1));
+			}
+			-26 => {
+				let a0 = pop!(parser, T3);
+ 				push!(parser, T3, Self::reduction25(a0));
+			}
+			-27 => {
+				let _ = parser.parse_stack.pop();
+				let a1 = pop!(parser, T3);
+ 				let _ = parser.parse_stack.pop();
+				push!(parser, T4, Self::reduction26(a1));
+			}
+			-28 => {
+				let a0 = pop!(parser, T1);
+ 				push!(parser, T4, Self::reduction27(a0));
+			}
+			-29 => {
+				let a0 = pop!(parser, T1);
+ 				push!(parser, T4, Self::reduction28(a0));
+			}
+			-30 => {
+				let a0 = pop!(parser, T1);
+ 				push!(parser, T4, Self::reduction29(a0));
+			}
+			-31 => {
+				let a0 = pop!(parser, T1);
+ 				push!(parser, T4, Self::reduction30(a0));
+			}
+			-32 => {
+				let _ = parser.parse_stack.pop();
+				let a1 = pop!(parser, T5);
+ 				let _ = parser.parse_stack.pop();
+				push!(parser, T4, Self::reduction31(a1));
+			}
+			-33 => {
+				let a0 = pop!(parser, T7);
+ 				push!(parser, T6, Self::reduction32(a0));
+			}
+			-34 => {
+				let a2 = pop!(parser, T6);
+ 				let _ = parser.parse_stack.pop();
+				let a0 = pop!(parser, T5);
+ 				push!(parser, T5, Self::reduction33(a0, a2));
+			}
+			-35 => {
+				let _ = parser.parse_stack.pop();
+				let a0 = pop!(parser, T4);
+ 				push!(parser, T7, Self::reduction34(a0));
+			}
+			-36 => {
+				let _ = parser.parse_stack.pop();
+				let a0 = pop!(parser, T4);
+ 				push!(parser, T7, Self::reduction35(a0));
+			}
+			-37 => {
+				let a1 = pop!(parser, T1);
+ 				let a0 = pop!(parser, T4);
+ 				push!(parser, T7, Self::reduction36(a0, a1));
+			}
+			-38 => {
+				let _ = parser.parse_stack.pop();
+				let a0 = pop!(parser, T4);
+ 				push!(parser, T7, Self::reduction37(a0));
+			}
+			-39 => {
+				let a0 = pop!(parser, T4);
+ 				push!(parser, T7, Self::reduction38(a0));
+			}
+			-40 => {
+				let a1 = pop!(parser, T7);
+ 				let a0 = pop!(parser, T6);
+ 				push!(parser, T6, Self::reduction39(a0, a1));
+			}
+			-41 => {
+				let a0 = pop!(parser, T2);
+ 				push!(parser, T4, Self::reduction40(a0));
+			}
+			-42 => {
 				let a0 = pop!(parser, T6);
- 				let _ = parser.state_stack.pop();
-				push!(parser, T6, Self::reduction18(a0));
+ 				push!(parser, T5, Self::reduction41(a0));
+			}
+			-43 => {
+				let a0 = pop!(parser, T5);
+ 				push!(parser, T6, Self::reduction42(a0));
 			}
 
-                new_state @ _ => {
-                    parser.state_stack.push((new_state-1) as usize);
+                new_state => {
+                    parser.state_stack.push((new_state-2) as usize);
                     push!(parser, T1, parser.lexer.slice());
                     token = match parser.lexer.next() {
                         Some(Ok(t)) => t as usize,
             Some(Err(e)) =>{
-                let mut line=0;
-                let mut offset=0;
-                let span = parser.lexer.span();
-                for c in parser.lexer.source()[0..span.end].chars(){
-                    if(c=='\n'){
-                        offset=0;
-                        line+=1;
-                    }
-                }
-
-                panic!("Unexpected Token {:?} ({:?}) at {}:{}", e, parser.lexer.slice(), line, offset);
+                let (line, column) = parser.location();
+                return Err(Error::Error(format!("{}:{}: Unexpected Input {:?} ({:?})", line, column, e, parser.lexer.slice())));
             },
                         None => 0
                     };
                     continue;
                 }
             }
-            let mut i = parser.state_stack.len();
-            while i>0 {
-                i-=1;
-                let prev = *parser.state_stack.get(i).unwrap();
-                let next = Self::GOTO[prev][-(task+1) as usize];
-                if next!=0 {
-                    parser.state_stack.push(next);
-                    break
-                }
-            }
-            if i<0{break}
+            let (nonterminal, len) = Self::REDUCTIONS[-(task+1) as usize];
+            parser.state_stack.truncate(parser.state_stack.len()-len);
+            let prev = *parser.state_stack.last().unwrap();
+            parser.state_stack.push(Self::GOTO[prev][nonterminal]);
         }
-        if parser.state_stack.len() != 1 {
-            panic!("Parsing failed! {:?} {:?}", parser.parse_stack, parser.state_stack);
-        } else {
-            match parser.parse_stack.into_iter().nth(0).unwrap() {
-                Types::T6(s) => s,
-                t@ _ => panic!("Parsing failed! {:?}", t)
-
-            }
+        match parser.parse_stack.pop() {
+            Some(Types::T6(s)) => Ok(s),
+            t => Err(Error::Error(format!("Parsing failed! {:?}", t)))
         }
     }
 }
 
 #[derive(Debug)]enum Types<'a> {
-	T4(Term),
-	T3(Vec<char>),
+	T5(Branches),
 	T1(&'a str),
+	T3(Vec<char>),
 	T2(char),
+	T4(Term),
 	T6(Vec<Regexpr>),
-	T5(Regexpr)
+	T7(Regexpr)
 }
 
+use std::collections::{btree_map, BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::rc::Rc;
+use std::collections::hash_map::Entry;
 
-// use std::fs::read_to_string;
-// fn main() {
-//     let source = match read_to_string("gramma.g") {
-//         Ok(s) => s,
-//         Err(e) => {
-//             panic!("cannot read file!")
-//         }
-//     };
-//     // println!("Input: {:?}", &string);
-//     let lex = Token::lexer(&source);
-//     println!("Result: {:?}", Parser::parse(lex));
-// }
+// parse the body of a grammar regex, eg. [0-9]+ for r"[0-9]+"
+pub fn parse(regex: &str) -> Result<Vec<Regexpr>, Error> {
+    let regex = Parser::parse(Token::lexer(regex))?;
+    check(&regex)?;
+    Ok(regex)
+}
 
-use std::{collections::{HashMap, HashSet, BTreeSet}, rc::Rc};
+fn check(regex: &[Regexpr]) -> Result<(), Error> {
+    for expr in regex {
+        let term = match expr {
+            Regexpr::Repeat(_, min, Some(max)) if max < min => {
+                return Err(Error::Error(format!("Invalid count {{{},{}}}", min, max)));
+            }
+            Regexpr::Match(t) | Regexpr::Maybe(t) | Regexpr::Any(t) | Regexpr::More(t) | Regexpr::Repeat(t, _, _) => t
+        };
+        match term {
+            Term::Pattern(r) => check(r)?,
+            Term::Or(branches) => for r in branches {
+                check(r)?;
+            },
+            _ => {}
+        }
+    }
+    Ok(())
+}
 
-struct Position{
-    regex: usize,
-    index: usize
+// regex matching the body of a grammar terminal, eg. if for "if"
+pub fn literal(terminal: &str) -> Vec<Regexpr> {
+    let mut regex = Vec::new();
+    let mut chars = terminal.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => chars.next().map_or('\\', escape_char),
+            c => c
+        };
+        regex.push(Regexpr::Match(Term::Char(c)));
+    }
+    regex
 }
 
-struct RegexPos{
-    stack: Vec<Position>
+// literal matching ASCII letters in either case
+pub fn literal_ignore_case(terminal: &str) -> Vec<Regexpr> {
+    literal(terminal).into_iter().map(|expr| match expr {
+        Regexpr::Match(Term::Char(c)) if c.is_ascii_alphabetic() =>
+            Regexpr::Match(Term::Group(vec![c.to_ascii_lowercase(), c.to_ascii_uppercase()])),
+        expr => expr
+    }).collect()
 }
 
-impl RegexPos {
-    fn get<'a>(&self, from: &'a Vec<Vec<Regexpr>>) -> Result<Option<&'a Regexpr>, Error> {
-        let pos = match self.stack.last() {
-            Some(t) => t,
-            None => return Ok(None)
-        };
+// the text of a literal body with its escapes resolved
+pub fn unescape(terminal: &str) -> String {
+    literal_text(&literal(terminal)).unwrap()
+}
+
+// the only string matched by a regex of plain chars
+fn literal_text(regex: &[Regexpr]) -> Option<String> {
+    regex.iter().map(|expr| match expr {
+        Regexpr::Match(Term::Char(c)) => Some(*c),
+        _ => None
+    }).collect()
+}
 
-        Ok(from.get(pos.regex).ok_or(
-            Error::Error(format!(
-                "Regex {} is out of range", pos.regex)))?
-            .get(pos.index))
+// true if the regex matches the empty string
+pub fn nullable(regex: &[Regexpr]) -> bool {
+    regex.iter().all(|expr| match expr {
+        Regexpr::Match(t) | Regexpr::More(t) => t.nullable(),
+        Regexpr::Maybe(_) | Regexpr::Any(_) => true,
+        Regexpr::Repeat(t, min, _) => *min == 0 || t.nullable()
+    })
+}
+
+impl Term {
+    fn nullable(&self) -> bool {
+        match self {
+            Term::Char(_) | Term::Group(_) | Term::NGroup(_) => false,
+            Term::Pattern(r) => nullable(r),
+            Term::Or(branches) => branches.iter().any(|r| nullable(r))
+        }
     }
-    fn next(&mut self, from: &Vec<Vec<Regexpr>>) {
-        match self.stack.last_mut() {
-            Some(e) => e.index +=1,
-            None => return
+
+    fn regex_syntax(&self) -> String {
+        match self {
+            Term::Char(c) => syntax_char(*c),
+            Term::Group(chars) => class_syntax(&char_ranges(chars)),
+            Term::NGroup(chars) => class_syntax(&negate_ranges(&char_ranges(chars))),
+            Term::Pattern(r) => format!("(?:{})", regex_syntax(r)),
+            Term::Or(branches) => format!("(?:{})", branches.iter().map(|r| regex_syntax(r)).collect::<Vec<_>>().join("|"))
         }
-        match self.get(from) {
-            Ok(Some(_)) | Err(_) => {}
-            Ok(None) => {
-                self.stack.pop();
-                self.next(from);
+    }
+}
+
+// the regex in the syntax of the regex crate, as read by logos
+pub fn regex_syntax(regex: &[Regexpr]) -> String {
+    regex.iter().map(|expr| match expr {
+        Regexpr::Match(t) => t.regex_syntax(),
+        Regexpr::Maybe(t) => t.regex_syntax() + "?",
+        Regexpr::Any(t) => t.regex_syntax() + "*",
+        Regexpr::More(t) => t.regex_syntax() + "+",
+        Regexpr::Repeat(t, min, Some(max)) => format!("{}{{{},{}}}", t.regex_syntax(), min, max),
+        Regexpr::Repeat(t, min, None) => format!("{}{{{},}}", t.regex_syntax(), min)
+    }).collect()
+}
+
+fn syntax_char(c: char) -> String {
+    match c {
+        c if "\\.+*?()|[]{}^$#&-~".contains(c) => format!("\\{}", c),
+        c if c.is_ascii_graphic() => c.to_string(),
+        c => format!("\\x{{{:x}}}", c as u32)
+    }
+}
+
+// logos matches negated classes and classes crossing 0x80 byte by byte,
+// so the ascii and the other ranges are spelled out in separate classes
+fn class_syntax(ranges: &[CharRange]) -> String {
+    let mut ascii = String::new();
+    let mut other = String::new();
+    for (a, b) in ranges {
+        // surrogates are no chars
+        let a = if (0xd800..0xe000).contains(a) { 0xe000 } else { *a };
+        let b = if (0xd800..0xe000).contains(b) { 0xd7ff } else { *b };
+        let splits = [(a, b.min(0x7f), &mut ascii), (a.max(0x80), b, &mut other)];
+        for (a, b, class) in splits {
+            if a > b {
+                continue
+            }
+            let (a, b) = (char::from_u32(a).unwrap(), char::from_u32(b).unwrap());
+            *class += &syntax_char(a);
+            if a != b {
+                *class += "-";
+                *class += &syntax_char(b);
             }
         }
     }
+    match (ascii.is_empty(), other.is_empty()) {
+        (false, true) => format!("[{}]", ascii),
+        (true, false) => format!("[{}]", other),
+        _ => format!("(?:[{}]|[{}])", ascii, other)
+    }
+}
+
+// inclusive range of unicode scalar values
+pub type CharRange = (u32, u32);
+
+const CHAR_MAX: u32 = char::MAX as u32;
+
+// sorted and merged ranges of a character list
+fn char_ranges(chars: &[char]) -> Vec<CharRange> {
+    let mut chars: Vec<u32> = chars.iter().map(|c| *c as u32).collect();
+    chars.sort();
+    chars.dedup();
+
+    let mut ranges: Vec<CharRange> = Vec::new();
+    for c in chars {
+        match ranges.last_mut() {
+            Some(last) if last.1+1 == c => last.1 = c,
+            _ => ranges.push((c, c))
+        }
+    }
+    ranges
 }
 
-#[derive(Default)]
-struct State{
-    result: usize,
-    next: HashMap<Term, usize>
+fn negate_ranges(ranges: &[CharRange]) -> Vec<CharRange> {
+    let mut res = Vec::new();
+    let mut start = 0;
+    for (a, b) in ranges {
+        if *a > start {
+            res.push((start, a-1));
+        }
+        start = b+1;
+    }
+    if start <= CHAR_MAX {
+        res.push((start, CHAR_MAX));
+    }
+    res
 }
 
+#[derive(Debug, Default)]
+pub struct NfaState {
+    pub accept: Option<usize>,
+    pub epsilon: Vec<usize>,
+    pub next: Vec<(Vec<CharRange>, usize)>
+}
 
-// TODO replace with global var in parser
-// not yet supported
-fn post_process(regexes: Vec<Vec<Regexpr>>) -> Vec<Vec<Regexpr>> {
-    let mut res = regexes.clone();
-    for regex in regexes {
-        for expr in regex {
-            match expr {
-                Regexpr::Any(mut t)
-                | Regexpr::Match(mut t)
-                | Regexpr::Maybe(mut t) =>{
-                    match t.clone() {
-                        Term::Pattern(p) => {
-                            t=Term::PatternImpl(res.len());
-                            res.push(p);
-                        }
-                        Term::Or(p1,p2) =>{
-                            let a=res.len();
-                            res.push(p1);
-                            t=Term::OrImpl(a, res.len());
-                            res.push(p2);
+// Thompson construction, state 0 is the start state
+#[derive(Debug, Default)]
+pub struct Nfa {
+    pub states: Vec<NfaState>
+}
+
+impl Nfa {
+    // the end state of regex i accepts token i
+    pub fn new(regexes: &[Vec<Regexpr>]) -> Nfa {
+        let mut nfa = Nfa{states: vec![NfaState::default()]};
+        for (token, regex) in regexes.iter().enumerate() {
+            let start = nfa.add_state();
+            nfa.states[0].epsilon.push(start);
+            let end = nfa.insert_regex(start, regex);
+            nfa.states[end].accept = Some(token);
+        }
+        nfa
+    }
+
+    fn add_state(&mut self) -> usize {
+        self.states.push(NfaState::default());
+        self.states.len()-1
+    }
+
+    fn add_ranges(&mut self, current: usize, ranges: Vec<CharRange>) -> usize {
+        let end = self.add_state();
+        self.states[current].next.push((ranges, end));
+        end
+    }
+
+    // every insert returns a fresh end state, loops only ever jump back to states created by themselves
+    fn insert_regex(&mut self, current: usize, regex: &[Regexpr]) -> usize {
+        regex.iter().fold(current, |current, expr| self.insert_expr(current, expr))
+    }
+
+    fn insert_expr(&mut self, current: usize, expr: &Regexpr) -> usize {
+        match expr {
+            Regexpr::Match(t) => self.insert_term(current, t),
+            Regexpr::Maybe(t) => {
+                let end = self.insert_term(current, t);
+                self.states[current].epsilon.push(end);
+                end
+            }
+            Regexpr::Any(t) => {
+                let start = self.add_state();
+                self.states[current].epsilon.push(start);
+                let body = self.insert_term(start, t);
+                self.states[body].epsilon.push(start);
+                let end = self.add_state();
+                self.states[start].epsilon.push(end);
+                end
+            }
+            Regexpr::More(t) => {
+                let start = self.add_state();
+                self.states[current].epsilon.push(start);
+                let body = self.insert_term(start, t);
+                self.states[body].epsilon.push(start);
+                let end = self.add_state();
+                self.states[body].epsilon.push(end);
+                end
+            }
+            Regexpr::Repeat(t, min, max) => {
+                let mut end = current;
+                for _ in 0..*min {
+                    end = self.insert_term(end, t);
+                }
+                match max {
+                    // every optional copy may skip to the common exit
+                    Some(max) => {
+                        let exit = self.add_state();
+                        for _ in *min..*max {
+                            self.states[end].epsilon.push(exit);
+                            end = self.insert_term(end, t);
                         }
-                        _ =>{}
+                        self.states[end].epsilon.push(exit);
+                        exit
                     }
-                },
-                _ => panic!()
+                    None => self.insert_expr(end, &Regexpr::Any(t.clone()))
+                }
             }
         }
     }
-    res
+
+    fn insert_term(&mut self, current: usize, term: &Term) -> usize {
+        match term {
+            Term::Char(c) => self.add_ranges(current, vec![(*c as u32, *c as u32)]),
+            Term::Group(chars) => self.add_ranges(current, char_ranges(chars)),
+            Term::NGroup(chars) => self.add_ranges(current, negate_ranges(&char_ranges(chars))),
+            Term::Pattern(regex) => self.insert_regex(current, regex),
+            Term::Or(branches) => {
+                let end = self.add_state();
+                for branch in branches {
+                    let branch_end = self.insert_regex(current, branch);
+                    self.states[branch_end].epsilon.push(end);
+                }
+                end
+            }
+        }
+    }
+
+    fn closure(&self, mut set: BTreeSet<usize>) -> BTreeSet<usize> {
+        let mut stack: Vec<usize> = set.iter().cloned().collect();
+        while let Some(state) = stack.pop() {
+            for next in &self.states[state].epsilon {
+                if set.insert(*next) {
+                    stack.push(*next);
+                }
+            }
+        }
+        set
+    }
 }
 
-// non stack variant
-pub struct DFA{
-    regex_list: Vec<Vec<Regexpr>>,
-    states: Vec<State>,
-    state_map: HashMap<BTreeSet<RegexPos>, usize>,
-    /*
-     * 1. Collect all possible tokens as strings => DFA
-     * 2. Read Quirks (usize) -> try to resolve Quirks
-     */
-    map: Vec<HashSet<Rc<str>>>
-}
-
-impl DFA {
-    // collect all tokens and create DFA
-    // obtain possible outputs from results list
-    // for r in map if token in r -> insert
-    pub fn new(regexes: Vec<Vec<Regexpr>>) -> Result<DFA, Error>
-    {
-        let mut dfa = DFA{
-            states: Vec::new(),
-            map: Vec::new(),
-            regex_list: regexes,
-            state_map: HashMap::new()
-        };
+// readable char of a range for witness strings
+fn witness_char((lo, hi): CharRange) -> char {
+    for (a, b) in [('a', 'z'), ('A', 'Z'), ('0', '9'), ('!', '~')] {
+        let (a, b) = (a as u32, b as u32);
+        if lo <= b && a <= hi {
+            return char::from_u32(lo.max(a)).unwrap();
+        }
+    }
+    char::from_u32(lo).or(char::from_u32(hi)).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+// append a transition, merging it into the last one if possible
+fn push_range(next: &mut Vec<(CharRange, usize)>, (lo, hi): CharRange, target: usize) {
+    match next.last_mut() {
+        Some(((_, last_hi), last)) if *last == target && *last_hi+1 == lo => *last_hi = hi,
+        _ => next.push(((lo, hi), target))
+    }
+}
 
+#[derive(Debug, Default)]
+pub struct DfaState {
+    pub accept: Option<usize>,
+    // all regexes matching in this state, the accepted one first
+    pub matches: Vec<usize>,
+    // sorted and disjoint
+    pub next: Vec<(CharRange, usize)>
+}
 
+// subset construction of the NFA, state 0 is the start state
+#[derive(Debug, Default)]
+pub struct Dfa {
+    pub states: Vec<DfaState>
+}
+
+impl Dfa {
+    // a state matching several regexes accepts the one listed first
+    pub fn new(regexes: Vec<Vec<Regexpr>>) -> Result<Dfa, Error> {
+        let dfa = Self::construct(&Nfa::new(&regexes));
+        if let Some(token) = dfa.states[0].accept {
+            return Err(Error::Error(format!("Regex {} matches the empty string", token)));
+        }
         Ok(dfa)
     }
 
-    fn append_next(map: &mut HashMap<Term, BTreeSet<RegexPos>>, term: Term, next: BTreeSet<RegexPos>) {
-        match term {
-            Term::Char(c) => {
+    fn construct(nfa: &Nfa) -> Dfa {
+        let mut dfa = Dfa::default();
+        let mut state_map: HashMap<BTreeSet<usize>, usize> = HashMap::new();
+        let mut queue = VecDeque::new();
+
+        let start = nfa.closure(BTreeSet::from([0]));
+        state_map.insert(start.clone(), 0);
+        dfa.states.push(DfaState::default());
+        queue.push_back(start);
 
+        while let Some(set) = queue.pop_front() {
+            let index = state_map[&set];
+            let matches: BTreeSet<usize> = set.iter().filter_map(|s| nfa.states[*s].accept).collect();
+            let matches: Vec<usize> = matches.into_iter().collect();
+            let accept = matches.first().cloned();
+
+            // split the alphabet at every range boundary of the outgoing transitions
+            let mut bounds = BTreeSet::new();
+            for (ranges, _) in set.iter().flat_map(|s| nfa.states[*s].next.iter()) {
+                for (a, b) in ranges {
+                    bounds.insert(*a);
+                    bounds.insert(b+1);
+                }
+            }
+            let bounds: Vec<u32> = bounds.into_iter().collect();
+
+            let mut next: Vec<(CharRange, usize)> = Vec::new();
+            for window in bounds.windows(2) {
+                let (lo, hi) = (window[0], window[1]-1);
+                let targets: BTreeSet<usize> = set.iter()
+                    .flat_map(|s| nfa.states[*s].next.iter())
+                    .filter(|(ranges, _)| ranges.iter().any(|(a, b)| *a<=lo && lo<=*b))
+                    .map(|(_, target)| *target)
+                    .collect();
+                if targets.is_empty() {
+                    continue;
+                }
+                let targets = nfa.closure(targets);
+                let target = match state_map.get(&targets) {
+                    Some(t) => *t,
+                    None => {
+                        let t = dfa.states.len();
+                        dfa.states.push(DfaState::default());
+                        state_map.insert(targets.clone(), t);
+                        queue.push_back(targets);
+                        t
+                    }
+                };
+                push_range(&mut next, (lo, hi), target);
             }
+
+            dfa.states[index] = DfaState{accept, matches, next};
         }
+        dfa
     }
 
-    fn add_state(&mut self, state: BTreeSet<RegexPos>) -> Result<(), Error> {
+    // shortest input reaching each state
+    fn witnesses(&self) -> Vec<Option<String>> {
+        let mut witnesses = vec![None; self.states.len()];
+        witnesses[0] = Some(String::new());
+        let mut queue = VecDeque::from([0]);
+        while let Some(state) = queue.pop_front() {
+            for (range, target) in &self.states[state].next {
+                if witnesses[*target].is_none() {
+                    let mut witness = witnesses[state].clone().unwrap();
+                    witness.push(witness_char(*range));
+                    witnesses[*target] = Some(witness);
+                    queue.push_back(*target);
+                }
+            }
+        }
+        witnesses
+    }
 
-        let next = HashMap::new();
-        // for all states
-        for pos in state {
-            if let Some(term) = pos.get(&self.regex_list)? {
-                match term {
-                    Regexpr::Any(t) => {
-                        // circle back
-                        //   Term->state
-                        // skip
-                        //   LastTerms->next
-                    }
-                    Regexpr::Maybe(t) => {
-                        // skip
-                        // match
-                        //   Term->next
-                    }
-                    Regexpr::Match(t) => {
-                        // match
-                    }
-                    Regexpr::More(t) => {
-                        // cirlce back
-                        // match
+    pub fn next(&self, state: usize, c: char) -> Option<usize> {
+        self.target(state, c as u32)
+    }
+
+    fn target(&self, state: usize, c: u32) -> Option<usize> {
+        let next = &self.states[state].next;
+        let i = next.partition_point(|((_, hi), _)| *hi < c);
+        next.get(i).filter(|((lo, _), _)| *lo <= c).map(|(_, target)| *target)
+    }
+
+    // longest prefix of the input accepted by the DFA as (token, length in bytes),
+    // what the generated lexers do with their tables
+    #[cfg(test)]
+    pub fn longest_match(&self, input: &str) -> Option<(usize, usize)> {
+        self.longest_expected_match(input, |_| true)
+    }
+
+    // longest prefix matched by an expected token, the first expected token of the state wins
+    #[cfg(test)]
+    pub fn longest_expected_match(&self, input: &str, expected: impl Fn(usize) -> bool) -> Option<(usize, usize)> {
+        let mut state = 0;
+        let mut res = None;
+        for (i, c) in input.char_indices() {
+            state = match self.next(state, c) {
+                Some(s) => s,
+                None => break
+            };
+            if let Some(token) = self.states[state].matches.iter().find(|t| expected(**t)) {
+                res = Some((*token, i+c.len_utf8()));
+            }
+        }
+        res
+    }
+    // split the alphabet into classes of chars that lead to the same state everywhere
+    pub fn classes(&self) -> CharClasses {
+        let mut bounds = BTreeSet::new();
+        for ((lo, hi), _) in self.states.iter().flat_map(|s| s.next.iter()) {
+            bounds.insert(*lo);
+            bounds.insert(hi+1);
+        }
+        let bounds: Vec<u32> = bounds.into_iter().collect();
+
+        let mut columns: HashMap<Vec<Option<usize>>, usize> = HashMap::new();
+        let mut classes = CharClasses::default();
+        for window in bounds.windows(2) {
+            let (lo, hi) = (window[0], window[1]-1);
+            let column: Vec<Option<usize>> = (0..self.states.len()).map(|s| self.target(s, lo)).collect();
+            if column.iter().all(|t| t.is_none()) {
+                continue;
+            }
+            let len = columns.len();
+            let class = *columns.entry(column).or_insert(len);
+            push_range(&mut classes.ranges, (lo, hi), class);
+        }
+        classes.count = columns.len();
+        classes
+    }
+
+    // transition table indexed by state and char class
+    pub fn table(&self, classes: &CharClasses) -> Vec<Vec<Option<usize>>> {
+        let mut first = vec![0; classes.count];
+        for ((lo, _), class) in classes.ranges.iter().rev() {
+            first[*class] = *lo;
+        }
+        (0..self.states.len()).map(|s| first.iter().map(|c| self.target(s, *c)).collect()).collect()
+    }
+
+    // Hopcroft's algorithm, states matching different tokens are never merged
+    pub fn minimize(&mut self) {
+        let classes = self.classes();
+        let table = self.table(&classes);
+
+        // missing transitions lead into an extra dead state
+        let dead = self.states.len();
+        let mut inverse = vec![vec![Vec::new(); dead+1]; classes.count];
+        for (s, row) in table.iter().enumerate() {
+            for (class, target) in row.iter().enumerate() {
+                inverse[class][target.unwrap_or(dead)].push(s);
+            }
+        }
+        for predecessors in inverse.iter_mut() {
+            predecessors[dead].push(dead);
+        }
+
+        let mut block_of = vec![0; dead+1];
+        let mut blocks: Vec<Vec<usize>> = Vec::new();
+        let mut initial = HashMap::new();
+        for (s, block_of_s) in block_of.iter_mut().enumerate() {
+            let matches = self.states.get(s).map(|state| state.matches.clone()).unwrap_or_default();
+            let block = match initial.entry(matches) {
+                Entry::Occupied(e) => *e.get(),
+                Entry::Vacant(e) => {
+                    blocks.push(Vec::new());
+                    *e.insert(blocks.len()-1)
+                }
+            };
+            blocks[block].push(s);
+            *block_of_s = block;
+        }
+
+        let mut pending: BTreeSet<(usize, usize)> = (0..blocks.len())
+            .flat_map(|b| (0..classes.count).map(move |c| (b, c)))
+            .collect();
+        while let Some((splitter, class)) = pending.pop_first() {
+            let mut touched: HashMap<usize, Vec<usize>> = HashMap::new();
+            for s in &blocks[splitter] {
+                for p in &inverse[class][*s] {
+                    touched.entry(block_of[*p]).or_default().push(*p);
+                }
+            }
+            for (block, inside) in touched {
+                if inside.len() == blocks[block].len() {
+                    continue;
+                }
+                let new = blocks.len();
+                for s in &inside {
+                    block_of[*s] = new;
+                }
+                blocks[block].retain(|s| block_of[*s] == block);
+                blocks.push(inside);
+                for c in 0..classes.count {
+                    if pending.contains(&(block, c)) || blocks[new].len() <= blocks[block].len() {
+                        pending.insert((new, c));
+                    } else {
+                        pending.insert((block, c));
                     }
-                    // last, current, next
                 }
+            }
+        }
 
+        // number blocks by their first state, states which can never accept are dropped
+        let dead_block = block_of[dead];
+        if block_of[0] == dead_block {
+            self.states = vec![DfaState::default()];
+            return;
+        }
+        let mut index = HashMap::new();
+        let mut representatives = Vec::new();
+        for (s, block) in block_of[..dead].iter().enumerate() {
+            if *block != dead_block && !index.contains_key(block) {
+                index.insert(*block, representatives.len());
+                representatives.push(s);
             }
         }
-        //     build Next paths
-        //     Compile to State
-        Ok(())
+
+        let states = representatives.iter().map(|s| {
+            let state = &self.states[*s];
+            let mut next = Vec::new();
+            for (range, target) in &state.next {
+                if let Some(t) = index.get(&block_of[*target]) {
+                    push_range(&mut next, *range, *t);
+                }
+            }
+            DfaState{accept: state.accept, matches: state.matches.clone(), next}
+        }).collect();
+        self.states = states;
     }
+}
 
+// partition of the alphabet, all chars of a class behave the same in every state
+#[derive(Debug, Default)]
+pub struct CharClasses {
+    // sorted and disjoint, chars outside of all ranges have no transitions
+    pub ranges: Vec<(CharRange, usize)>,
+    pub count: usize
 }
 
-/*
- * State: HashMap<Term, State>
- * stack self until opaque nöööö
- */
+impl CharClasses {
+    #[cfg(test)]
+    pub fn class(&self, c: char) -> Option<usize> {
+        let c = c as u32;
+        let i = self.ranges.partition_point(|((_, hi), _)| *hi < c);
+        self.ranges.get(i).filter(|((lo, _), _)| *lo <= c).map(|(_, class)| *class)
+    }
+}
 
-impl Regexpr {
-    fn opaque(&self) -> bool {
-        match self {
-            Regexpr::Match(_)=>true,
-            Regexpr::More(_)=>true,
-            Regexpr::Any(_) =>false,
-            Regexpr::Maybe(_)=>false
-        }
+// terminal of a grammar component
+fn terminal(component: &parser::Component0) -> Option<lr::Token> {
+    match component {
+        parser::Component0::Terminal(t) => Some(lr::Token::Terminal(t.clone())),
+        parser::Component0::Regex(r) => Some(lr::Token::Regex(r.clone())),
+        parser::Component0::Insensitive(t) => Some(lr::Token::Insensitive(t.clone())),
+        _ => None
     }
 }
 
+// change of the lexer mode after a token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeChange {
+    Stay,
+    Push(usize),
+    Pop
+}
+
+// longest match lexer for the terminals of one lexer mode
+//
+// on matches of equal length literal terminals win over regexes,
+// otherwise the token declared first in the grammar wins
+pub struct LexerMode {
+    pub name: Rc<str>,
+    pub dfa: Dfa,
+    // automaton terminal of every dfa token, highest priority first
+    pub tokens: Vec<usize>,
+    // mode change after every automaton terminal
+    pub transitions: Vec<ModeChange>,
+    regexes: Vec<Vec<Regexpr>>,
+    // dfa tokens which are literal terminals
+    literals: Vec<bool>
+}
 
-pub struct NDAState {
-    fin: Option<usize>,
-    next: HashSet<NDAState>
+// one lexer mode per %mode declaration, mode 0 is the default mode
+// holding all terminals not declared in a mode
+//
+// skipped tokens are numbered after the automaton terminals, a mode declaring
+// skipped tokens of its own skips only those
+pub struct Lexer {
+    pub modes: Vec<LexerMode>,
+    pub skip: Vec<lr::Token>,
+    // produce the synthetic terminals from the indentation
+    pub indent: bool,
+    terminals: usize
 }
 
-struct NDA {
-   start: Vec<NDAState>
+// two terminals matching the same input
+#[derive(Debug)]
+pub struct Overlap {
+    pub tokens: (usize, usize),
+    // shortest input matched by both
+    pub witness: String,
+    // terminal the lexer produces for the witness, the higher priority one of the pair
+    // unless a third terminal wins on every input both match
+    pub winner: usize
 }
-impl NDA
-{
-    pub fn new(regexes: HashSet<Vec<Regexpr>>) -> Result<NDA, Error>
-    {
-        let mut nda = NDA{
-            start: Vec::new()
-        };
 
-        for regex in regexes {
-            self.insert(regex)?;
+impl Lexer {
+    pub fn new(terminals: &[lr::Token], ast: &parser::GAst) -> Result<Lexer, Error> {
+        let mut declared: HashMap<lr::Token, usize> = HashMap::new();
+        for component in ast.rules.iter()
+            .flat_map(|r| r.reductends.reductends.iter())
+            .flat_map(|r| r.components.components.iter())
+        {
+            if let Some(token) = terminal(&component.handle) {
+                let len = declared.len();
+                declared.entry(token).or_insert(len);
+            }
+        }
+
+        if let Some(s) = terminals.iter().find(|t| matches!(t, lr::Token::Synthetic(_))) {
+            if !ast.indent {
+                return Err(Error::Error(format!("{} is only produced with %indent", s)));
+            }
         }
 
+        let mut skip: Vec<lr::Token> = Vec::new();
+        let default_skip = vec![parser::Component0::Terminal("\" \"".into())];
+        for component in ast.skip.as_ref().unwrap_or(&default_skip) {
+            let token = terminal(component).ok_or_else(|| Error::Error("%skip may only contain terminals".to_string()))?;
+            if terminals.contains(&token) {
+                return Err(Error::Error(format!("Token {} is used by the grammar and cannot be skipped", token)));
+            }
+            if !skip.contains(&token) {
+                skip.push(token);
+            }
+        }
+        let terminals_len = terminals.len();
+        let all: Vec<lr::Token> = terminals.iter().chain(skip.iter()).cloned().collect();
+        let terminals = &all;
 
-        Ok(nda)
+        let mut names: Vec<Rc<str>> = vec!["default".into()];
+        for mode in &ast.modes {
+            if !names.contains(&mode.name) {
+                names.push(mode.name.clone());
+            }
+        }
+
+        // terminals and transitions of every mode, declarations of the same mode are merged
+        let mut members = vec![Vec::new(); names.len()];
+        let mut transitions = vec![vec![ModeChange::Stay; terminals.len()]; names.len()];
+        let mut in_mode = vec![false; terminals.len()];
+        for mode in &ast.modes {
+            let m = names.iter().position(|n| *n == mode.name).unwrap();
+            for token in &mode.tokens {
+                let token_name = terminal(&token.token)
+                    .ok_or_else(|| Error::Error(format!("Mode {} may only contain terminals", mode.name)))?;
+                let t = terminals.iter().position(|o| *o == token_name)
+                    .ok_or_else(|| Error::Error(format!("Token {} of mode {} is neither used by the grammar nor skipped", token_name, mode.name)))?;
+                transitions[m][t] = match &token.transition {
+                    parser::Transition::Stay => ModeChange::Stay,
+                    parser::Transition::Pop => ModeChange::Pop,
+                    parser::Transition::Push(target) => ModeChange::Push(names.iter().position(|n| n == target)
+                        .ok_or_else(|| Error::Error(format!("Unknown mode {} in mode {}", target, mode.name)))?)
+                };
+                if !members[m].contains(&t) {
+                    members[m].push(t);
+                }
+                in_mode[t] = true;
+            }
+        }
+        // skipped tokens not declared in a mode are skipped in every mode that declares no skipped tokens of its own
+        let own_skip: Vec<bool> = members.iter().map(|tokens| tokens.iter().any(|t| *t >= terminals_len)).collect();
+        for t in 0..terminals.len() {
+            let modes: Vec<usize> = if t >= terminals_len {(0..members.len()).filter(|m| !own_skip[*m]).collect()} else {vec![0]};
+            for m in modes {
+                let lexed = !matches!(terminals[t], lr::Token::EOF | lr::Token::Synthetic(_));
+                if !in_mode[t] && lexed && !members[m].contains(&t) {
+                    members[m].push(t);
+                }
+            }
+        }
+
+        let modes = names.into_iter().zip(members).zip(transitions).map(|((name, mut tokens), transitions)| {
+            tokens.sort_by_key(|t| (
+                matches!(terminals[*t], lr::Token::Regex(_)),
+                declared.get(&terminals[*t]).cloned().unwrap_or(usize::MAX),
+                *t
+            ));
+            LexerMode::new(name, terminals, tokens, transitions)
+        }).collect::<Result<_, _>>()?;
+        Ok(Lexer{modes, skip, indent: ast.indent, terminals: terminals_len})
     }
-    fn insert_regex(&mut self, mut current: usize, regex: Vec<Regexpr>, mut stack: Vec<usize>) -> Result<(), Error> {
-        for expr in regex {
 
-            // add self to all stack items
-            for next in stack {
-                next.mu
+    // for every literal terminal the other terminals matching its whole text in a mode containing it,
+    // highest priority first
+    //
+    // generated parsers fall back to these where the literal has no action,
+    // so a keyword can still be an identifier where the grammar does not expect the keyword
+    //
+    // the dfa lexers already only produce the terminals the state expects, the logos lexers are
+    // not restricted and only whole tokens are reinterpreted: ">>" stays one token there
+    pub fn alternatives(&self) -> Vec<Vec<usize>> {
+        let mut alternatives: Vec<Vec<usize>> = vec![Vec::new(); self.terminals];
+        for mode in &self.modes {
+            for (t, mut other) in mode.alternatives() {
+                if t >= self.terminals {
+                    continue;
+                }
+                other.retain(|o| *o < self.terminals && !alternatives[t].contains(o));
+                alternatives[t].extend(other);
             }
+        }
+        alternatives
+    }
+}
 
-            // add stack?
-            if !expr.opaque() {
-                stack.push(current);
+impl LexerMode {
+    fn new(name: Rc<str>, terminals: &[lr::Token], tokens: Vec<usize>, transitions: Vec<ModeChange>) -> Result<LexerMode, Error> {
+        let mut regexes = Vec::new();
+        let mut literals = Vec::new();
+        for t in &tokens {
+            let token = &terminals[*t];
+            let regex = match token {
+                lr::Token::Terminal(s) => literal(&s[1..s.len()-1]),
+                lr::Token::Insensitive(s) => literal_ignore_case(&s[2..s.len()-1]),
+                lr::Token::Regex(r) => parse(&r[2..r.len()-1])
+                    .map_err(|e| Error::Error(format!("Invalid regex {}: {:?}", token, e)))?,
+                lr::Token::EOF | lr::Token::Synthetic(_) => unreachable!()
+            };
+            if nullable(&regex) {
+                return Err(Error::Error(format!("Token {} matches the empty string", token)));
             }
+            literals.push(matches!(token, lr::Token::Terminal(_) | lr::Token::Insensitive(_)));
+            regexes.push(regex);
+        }
+
+        let mut dfa = Dfa::new(regexes.clone())?;
+        dfa.minimize();
+        Ok(LexerMode{name, dfa, tokens, transitions, regexes, literals})
+    }
 
-            match expr {
-                Regexpr::Any(t) => {
+    // pairs of terminals whose languages intersect
+    pub fn overlaps(&self) -> Vec<Overlap> {
+        let dfa = Dfa::construct(&Nfa::new(&self.regexes));
+        let witnesses = dfa.witnesses();
 
+        // states are numbered in breadth first order, so the first witness of a pair is a shortest one
+        // a witness lexed as the pair's own winner is preferred over one a third terminal takes
+        let mut found: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+        for (state, dfa_state) in dfa.states.iter().enumerate() {
+            let matching = &dfa_state.matches;
+            for (i, a) in matching.iter().enumerate() {
+                for b in &matching[i+1..] {
+                    match found.entry((*a, *b)) {
+                        btree_map::Entry::Vacant(e) => {
+                            e.insert(state);
+                        }
+                        btree_map::Entry::Occupied(mut e) => if dfa.states[*e.get()].accept != Some(*a) && dfa.states[state].accept == Some(*a) {
+                            e.insert(state);
+                        }
+                    }
                 }
             }
         }
-        Ok(())
+        found.into_iter().map(|((a, b), state)| Overlap{
+            tokens: (self.tokens[a], self.tokens[b]),
+            witness: witnesses[state].clone().unwrap(),
+            winner: self.tokens[dfa.states[state].accept.unwrap()]
+        }).collect()
+    }
+
+    // literal terminals of this mode with the other terminals matching their whole text,
+    // in every spelling of a case insensitive literal
+    fn alternatives(&self) -> Vec<(usize, Vec<usize>)> {
+        let dfa = Dfa::construct(&Nfa::new(&self.regexes));
+        let mut alternatives = Vec::new();
+        for (i, regex) in self.regexes.iter().enumerate().filter(|(i, _)| self.literals[*i]) {
+            // the states reached by the spellings of the text so far
+            let mut states = BTreeSet::from([0]);
+            for expr in regex {
+                let chars = match expr {
+                    Regexpr::Match(Term::Char(c)) => vec![*c],
+                    Regexpr::Match(Term::Group(chars)) => chars.clone(),
+                    _ => unreachable!()
+                };
+                states = states.iter().flat_map(|s| chars.iter().filter_map(|c| dfa.next(*s, *c))).collect();
+            }
+            let other = (0..self.tokens.len())
+                .filter(|t| *t != i && states.iter().all(|s| dfa.states[*s].matches.contains(t)))
+                .map(|t| self.tokens[t]).collect();
+            alternatives.push((self.tokens[i], other));
+        }
+        alternatives
+    }
+
+    // terminals which are never produced, together with the terminals winning over them
+    pub fn shadowed(&self) -> Vec<(usize, Vec<usize>)> {
+        let dfa = Dfa::construct(&Nfa::new(&self.regexes));
+        let produced: BTreeSet<usize> = dfa.states.iter().filter_map(|s| s.accept).collect();
+        (0..self.tokens.len()).filter(|t| !produced.contains(t)).map(|t| {
+            let winners: BTreeSet<usize> = dfa.states.iter()
+                .filter(|state| state.matches.contains(&t))
+                .filter_map(|state| state.accept)
+                .collect();
+            (self.tokens[t], winners.into_iter().map(|w| self.tokens[w]).collect())
+        }).collect()
     }
 }
 
-/*
- *
- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dfa(regexes: &[&str]) -> Dfa {
+        Dfa::new(regexes.iter().map(|r| parse(r).unwrap()).collect()).unwrap()
+    }
+
+    #[test]
+    fn longest_match() {
+        let dfa = dfa(&["[0-9]+", "[a-z][a-z0-9]*", r"\s+", "a|bc", "(ab)*c"]);
+        assert_eq!(dfa.longest_match("123abc"), Some((0, 3)));
+        assert_eq!(dfa.longest_match("x1 y"), Some((1, 2)));
+        assert_eq!(dfa.longest_match(" \t\n1"), Some((2, 3)));
+        assert_eq!(dfa.longest_match("ababc"), Some((1, 5)));
+        assert_eq!(dfa.longest_match("ABC"), None);
+        assert_eq!(dfa.longest_match(""), None);
+    }
+
+    #[test]
+    fn classes_and_repetition() {
+        let dfa = dfa(&[r"[\w-]+!", "[^a-c]{2,3}", "[-a]"]);
+        assert_eq!(dfa.longest_match("a-b_9!x"), Some((0, 6)));
+        assert_eq!(dfa.longest_match("xyzw"), Some((1, 3)));
+        assert_eq!(dfa.longest_match("-"), Some((2, 1)));
+        assert_eq!(dfa.longest_match("äö"), Some((1, 4)));
+    }
+
+    #[test]
+    fn keyword_before_identifier() {
+        let dfa = Dfa::new(vec![literal("if"), parse("[a-z]+").unwrap()]).unwrap();
+        assert_eq!(dfa.longest_match("if("), Some((0, 2)));
+        assert_eq!(dfa.longest_match("iff"), Some((1, 3)));
+        assert_eq!(dfa.longest_match("i"), Some((1, 1)));
+    }
+
+    #[test]
+    fn minimize_keeps_matches() {
+        let inputs = ["", "a", "ab", "abb", "aabb", "babb", "abab", "if", "iff", "x_1", "12", "1.5e3", "_"];
+        let mut dfa = Dfa::new(vec![
+            literal("if"),
+            parse("(a|b)*abb").unwrap(),
+            parse("[a-z_][a-z0-9_]*").unwrap(),
+            parse("[0-9]+(.[0-9]+)?(e[0-9]+)?").unwrap(),
+        ]).unwrap();
+        let before: Vec<_> = inputs.iter().map(|i| dfa.longest_match(i)).collect();
+        let states = dfa.states.len();
+        dfa.minimize();
+        let after: Vec<_> = inputs.iter().map(|i| dfa.longest_match(i)).collect();
+        assert_eq!(before, after);
+        assert!(dfa.states.len() < states);
+        assert_eq!(dfa.longest_match("babb"), Some((1, 4)));
+        assert_eq!(dfa.longest_match("babba"), Some((2, 5)));
+    }
+
+    #[test]
+    fn minimize_merges_equivalent_states() {
+        // both branches end in the same accepting state
+        let mut dfa = dfa(&["ac|bc"]);
+        dfa.minimize();
+        assert_eq!(dfa.states.len(), 3);
+        assert_eq!(dfa.longest_match("bc"), Some((0, 2)));
+    }
+
+    #[test]
+    fn classes_match_transitions() {
+        let dfa = dfa(&["[a-z]+", "[0-9]+", "if", r"\."]);
+        let classes = dfa.classes();
+        let table = dfa.table(&classes);
+        for (state, row) in table.iter().enumerate() {
+            for c in ['a', 'f', 'i', 'z', '0', '9', '\\', '.', ' ', 'ä'] {
+                let target = classes.class(c).and_then(|class| row[class]);
+                assert_eq!(target, dfa.next(state, c), "state {} char {:?}", state, c);
+            }
+        }
+        // all letters but the ones of "if" behave the same
+        assert_eq!(classes.class('a'), classes.class('z'));
+        assert_ne!(classes.class('a'), classes.class('i'));
+        assert_eq!(classes.class(' '), None);
+    }
+
+    fn accepts(dfa: &Dfa, input: &str) -> bool {
+        dfa.longest_match(input).map(|(_, len)| len) == Some(input.len())
+    }
+
+    #[test]
+    fn nested_loops() {
+        let optional = dfa(&["a(bc*)?d"]);
+        for input in ["ad", "abd", "abcd", "abccd"] {
+            assert!(accepts(&optional, input), "{}", input);
+        }
+        for input in ["acd", "abcbd", "a"] {
+            assert!(!accepts(&optional, input), "{}", input);
+        }
+
+        let counted = dfa(&["x(ab*){0,2}"]);
+        for input in ["x", "xa", "xabb", "xaba", "xabbab"] {
+            assert!(accepts(&counted, input), "{}", input);
+        }
+        for input in ["xb", "xaaa", "xababa"] {
+            assert!(!accepts(&counted, input), "{}", input);
+        }
+
+        let nested = dfa(&["(ab+)?c", "y(a*|b)+z"]);
+        for input in ["c", "abc", "abbc", "yz", "yaabz", "ybbz"] {
+            assert!(accepts(&nested, input), "{}", input);
+        }
+        for input in ["bc", "ac", "yc"] {
+            assert!(!accepts(&nested, input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn unicode_and_operators_in_classes() {
+        let dfa = dfa(&["[a-zé]+", "[+*.()|?]", r"\é[ü-]"]);
+        assert_eq!(dfa.longest_match("éaé!"), Some((0, 5)));
+        assert_eq!(dfa.longest_match("(x"), Some((1, 1)));
+        assert_eq!(dfa.longest_match("|"), Some((1, 1)));
+        assert_eq!(dfa.longest_match("éü"), Some((2, 4)));
+        assert_eq!(dfa.longest_match("b"), Some((0, 1)));
+    }
+
+    #[test]
+    fn empty_match_is_an_error() {
+        assert!(Dfa::new(vec![parse("a*").unwrap()]).is_err());
+    }
+
+    fn lexer(grammar: &str) -> (Vec<lr::Token>, Lexer) {
+        use logos::Logos;
+        let ast = crate::parser::parse(crate::parser::gTokens::lexer(grammar)).unwrap();
+        let automaton = crate::automaton::Automaton::new(&lr::LR::new(&ast.rules).unwrap()).unwrap();
+        let lexer = Lexer::new(&automaton.terminals, &ast).unwrap();
+        (automaton.terminals.iter().chain(lexer.skip.iter()).cloned().collect(), lexer)
+    }
+
+    #[test]
+    fn modes_with_own_skips_do_not_inherit() {
+        let (terminals, lexer) = lexer(r#"
+            %skip " " r"\\\n";
+            %mode default: "\"" -> string "[" -> list;
+            %mode string: r"[a-z ]+" r"\\\n" "\"" <-;
+            %mode list: r"[0-9]+" "]" <-;
+            start: "\"" r"[a-z ]+"=s "\"" {s.to_string()} | "[" r"[0-9]+"=s "]" {s.to_string()} -> String;
+        "#);
+        let space = terminals.iter().position(|t| *t == lr::Token::Terminal("\" \"".into())).unwrap();
+        let skipped = |name: &str| lexer.modes.iter().find(|m| &*m.name == name).unwrap().tokens.contains(&space);
+        assert!(skipped("default"));
+        assert!(skipped("list"));
+        assert!(!skipped("string"));
+    }
+
+    #[test]
+    fn expected_tokens_only() {
+        let tokens = dfa(&[">", ">>", "if", "[a-z]+", "[a-z]+!"]);
+        assert_eq!(tokens.longest_expected_match(">>", |t| t == 0), Some((0, 1)));
+        assert_eq!(tokens.longest_expected_match("if", |t| t != 2), Some((3, 2)));
+        // the longest expected match wins over a longer unexpected one
+        assert_eq!(tokens.longest_expected_match("ab!", |t| t == 3), Some((3, 2)));
+        assert_eq!(tokens.longest_expected_match("ab!", |_| false), None);
+
+        let mut minimal = dfa(&["if", "[a-z]+"]);
+        minimal.minimize();
+        assert_eq!(minimal.longest_expected_match("if", |t| t == 1), Some((1, 2)));
+    }
+
+    #[test]
+    fn alternatives_match_every_spelling() {
+        let (terminals, lexer) = lexer(r#"
+            start: i"select" {0} | r"[a-z]+" {1} | r"[A-Za-z]+" {2} -> usize;
+        "#);
+        let index = |name: &str| terminals.iter().position(|t| t.to_string() == name).unwrap();
+        assert_eq!(lexer.alternatives()[index(r#"i"select""#)], [index(r#"r"[A-Za-z]+""#)]);
+    }
+
+    #[test]
+    fn regex_crate_syntax() {
+        let syntax = |regex| regex_syntax(&parse(regex).unwrap());
+        assert_eq!(syntax(r#"[^\"]+"#), r"(?:[\x{0}-!\#-\x{7f}]|[\x{80}-\x{10ffff}])+");
+        assert_eq!(syntax(r"\d\w?"), "[0-9][0-9A-Z_a-z]?");
+        assert_eq!(syntax(r"a.{2,}"), r"a(?:[\x{0}-\x{9}\x{b}-\x{7f}]|[\x{80}-\x{10ffff}]){2,}");
+        assert_eq!(syntax(r"(a|\+)*[é-ü]"), r"(?:a|\+)*[\x{e9}-\x{fc}]");
+    }
+}
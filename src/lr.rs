@@ -1,13 +1,28 @@
 use crate::parser;
 use std::{rc::Rc, collections::{HashMap, BTreeMap, BTreeSet, HashSet}};
+use serde::{Serialize, Deserialize};
 
 type IdxRule = usize;
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, PartialOrd, Ord)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Token {
     Terminal(Rc<str>),
     Regex(Rc<str>),
-    EOF
+    EOF,
+    Insensitive(Rc<str>),
+    Synthetic(parser::Synthetic)
+}
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Terminal(t) => write!(f, "{}", t),
+            Token::Regex(r) => write!(f, "{}", r),
+            Token::Insensitive(t) => write!(f, "{}", t),
+            Token::EOF => write!(f, "EOF"),
+            Token::Synthetic(s) => write!(f, "{}", s),
+        }
+    }
 }
 #[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ReductendPosition{
@@ -54,7 +69,7 @@ pub struct GrammarError {
 
 }
 
-#[derive(Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
 pub struct Position {
     pub rule: IdxRule,
     pub reductend: usize,
@@ -71,17 +86,17 @@ impl Position{
         })
 
     }
-    pub fn rule_ref<'a>(rules: &'a Vec<parser::Rule>, rule: &str) -> Result<&'a parser::Rule, Error> {
+    pub fn rule_ref<'a>(rules: &'a [parser::Rule], rule: &str) -> Result<&'a parser::Rule, Error> {
         rules.iter().find(|e| e.identifier==rule.into()).ok_or(Error::Error(format!("Rule {} does not found!", rule)))
     }
-    pub fn rule_index(rules: &Vec<parser::Rule>, rule: &str) -> Result<IdxRule, Error> {
+    pub fn rule_index(rules: &[parser::Rule], rule: &str) -> Result<IdxRule, Error> {
         rules.iter().position(|e| e.identifier==rule.into()).ok_or(Error::Error(format!("Rule {} does not found!", rule)))
     }
-    pub fn get<'a>(&self, rules: &'a Vec<parser::Rule>) -> Option<&'a parser::Component> {
+    pub fn get<'a>(&self, rules: &'a [parser::Rule]) -> Option<&'a parser::Component> {
         let (_, reductend) = self.get_rr(rules)?;
         reductend.components.components.get(self.component)
     }
-    pub fn get_rr<'a>(&self, rules: &'a Vec<parser::Rule>) -> Option<(&'a parser::Rule, &'a parser::Reductend)> {
+    pub fn get_rr<'a>(&self, rules: &'a [parser::Rule]) -> Option<(&'a parser::Rule, &'a parser::Reductend)> {
         let rule  = rules.get(self.rule)?;
         let reductend = rule.reductends.reductends.get(self.reductend)?;
         Some((rule, reductend))
@@ -94,17 +109,19 @@ impl Position{
     fn item_write(mut string: String, c: &parser::Component) -> String {
         string += " ";
         string += match &c.handle {
-            parser::Component0::Regex(r) =>r,
-            parser::Component0::Terminal(t) =>t,
+            parser::Component0::Regex(r) =>r.to_string(),
+            parser::Component0::Terminal(t) =>t.to_string(),
+            parser::Component0::Insensitive(t) =>t.to_string(),
+            parser::Component0::Synthetic(s) =>s.to_string(),
             parser::Component0::Token =>panic!("not implemented!"),
-            parser::Component0::Rule(r)=>r
-        }.to_string().as_str();
+            parser::Component0::Rule(r)=>r.to_string()
+        }.as_str();
         string
     }
 
     pub fn get_string(&self, rules: &Vec<parser::Rule>) -> String {
         let (rule, reductend) = self.get_rr(rules).unwrap();
-        Self::get_item(&rule, &reductend, self.component)
+        Self::get_item(rule, reductend, self.component)
     }
     fn get_item(rule: &parser::Rule, reductend: &parser::Reductend, component_index: usize) -> String {
         let mut string = rule.identifier.to_string() + " ->";
@@ -113,7 +130,7 @@ impl Position{
             if i == component_index {
                 string += " •";
             }
-            string = Self::item_write(string, &c);
+            string = Self::item_write(string, c);
             i+=1;
         }
         if i == component_index {
@@ -124,7 +141,8 @@ impl Position{
     }
 }
 
-#[derive(Hash, Clone, PartialEq, Eq, Debug, PartialOrd, Ord, Default)]
+#[derive(Hash, Clone, PartialEq, Eq, Debug, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Positions(BTreeSet<Position>);
 
 impl IntoIterator for Positions {
@@ -157,12 +175,12 @@ impl Positions {
         Ok(())
     }
     pub fn merge(&mut self, other: Self){
-        self.0.union(&other.0);
+        self.0.extend(other.0);
     }
     fn from(rules: &Vec<parser::Rule>, rule: &str) -> Result<Self, Error> {
         let mut set = Self(BTreeSet::new());
         set.add_rule(rules, rule)?;
-        return Ok(set);
+        Ok(set)
     }
     pub fn get_string(&self, rules: &Vec<parser::Rule>) -> String {
         let items = self.iter().map(|p| p.get_string(rules)).collect::<Vec<_>>();
@@ -194,9 +212,18 @@ enum Event {
 #[derive(Clone, Default)]
 pub struct State {
     pub next: HashMap<Token, StateHead>,
-    pub goto: HashMap<ReductendPosition, StateHead>,
+    pub goto: HashMap<IdxRule, StateHead>,
     pub reduce: HashMap<Token, BTreeSet<ReductendPosition>>
 }
+impl State {
+    // tokens with more than one possible action
+    pub fn conflicts(&self) -> BTreeSet<Token> {
+        self.reduce.iter()
+            .filter(|(token, reductends)| reductends.len()>1 || self.next.contains_key(token))
+            .map(|(token, _)| token.clone())
+            .collect()
+    }
+}
 impl<'a> LR<'a> {
 
     pub fn new(rules: &'a Vec<parser::Rule>) -> Result<Self, Error> {
@@ -223,6 +250,28 @@ impl<'a> LR<'a> {
         Ok(lr)
     }
 
+    // all states in breadth first order from the start state
+    // shifts are followed before gotos, both sorted, so the numbering is stable between runs
+    pub fn states(&self) -> Vec<&StateHead> {
+        let mut order = vec![&self.start];
+        let mut visited = HashSet::from([&self.start]);
+        let mut i = 0;
+        while let Some(head) = order.get(i) {
+            let state = &self.state_map[*head];
+            let mut next: Vec<_> = state.next.iter().collect();
+            let mut goto: Vec<_> = state.goto.iter().collect();
+            next.sort();
+            goto.sort();
+            for child in next.into_iter().map(|(_, h)| h).chain(goto.into_iter().map(|(_, h)| h)) {
+                if visited.insert(child) {
+                    order.push(child);
+                }
+            }
+            i+=1;
+        }
+        order
+    }
+
     // insert all expected tokens into the Set recursively
     fn collect_tokens(rules: &'a Vec<parser::Rule>, position: Position, tokens: &mut BTreeSet<Token>, visited: &mut HashSet<Rc<str>>) -> Result<(), Error> {
         match Self::next_event(&position, rules) {
@@ -247,7 +296,7 @@ impl<'a> LR<'a> {
     fn _normalize_head(rules: &'a Vec<parser::Rule>, expand: StateHead, out: &mut StateHead) -> Result<(),Error>{
         for (pos, import) in expand {
             let out_import = out.entry(pos.clone()).or_default();
-            if import.is_subset(&out_import) {
+            if import.is_subset(out_import) {
                 continue;
             }
             out_import.extend(import.clone());
@@ -319,7 +368,7 @@ impl<'a> LR<'a> {
     }
     fn impl_path(&self, frag: (&Position, &BTreeSet<Token>), state: &mut State) -> Result<(), Error> {
 
-        match Self::next_event(&frag.0, self.rules) {
+        match Self::next_event(frag.0, self.rules) {
             Event::Shift(token) => {
                 // append path to next state for token
                 Self::insert_next(&mut state.next, frag, token);
@@ -333,10 +382,9 @@ impl<'a> LR<'a> {
                 }
             }
             Event::Rule(r) => {
-                // insert return statements
-                for branch in Positions::from(self.rules, &r)?{
-                    Self::insert_next(&mut state.goto, frag, branch.into());
-                }
+                // insert return statement
+                let rule = Position::rule_index(self.rules, &r)?;
+                Self::insert_next(&mut state.goto, frag, rule);
             }
         }
         Ok(())
@@ -357,6 +405,12 @@ impl<'a> LR<'a> {
                 parser::Component0::Terminal(t) => {
                     Event::Shift(Token::Terminal(t.clone()))
                 }
+                parser::Component0::Insensitive(t) => {
+                    Event::Shift(Token::Insensitive(t.clone()))
+                }
+                parser::Component0::Synthetic(s) => {
+                    Event::Shift(Token::Synthetic(*s))
+                }
                 parser::Component0::Rule(r) => {
                     Event::Rule(r.clone())
                 }
@@ -1,6 +1,5 @@
 use logos::Logos;
-use std::collections::{BTreeSet, HashSet};
-use std::{rc::Rc, fs::read_to_string};
+use std::fs::read_to_string;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
@@ -9,7 +8,9 @@ mod parser;
 mod lr;
 mod automaton;
 mod reverseparse;
-// mod lexer;
+mod report;
+mod json;
+mod lexer;
 
 fn info(lr: &lr::LR, ast: &parser::GAst) {
     // print table
@@ -43,9 +44,9 @@ fn info(lr: &lr::LR, ast: &parser::GAst) {
 
     for (p, s) in &lr.state_map {
         idx.push(get_insert(p.clone()).to_string());
-        positions.extend(p.iter().map(|(position, _)| position.get_string(&ast.rules)));
+        positions.extend(p.keys().map(|position| position.get_string(&ast.rules)));
         next.extend(s.next.iter().map(|(t, p)| format!("{:?}: {}", t, get_insert(p.clone()))));
-        goto.extend(s.goto.iter().map(|(r, p)| format!("{},{}: {}", r.rule, r.reductend, get_insert(p.clone()))));
+        goto.extend(s.goto.iter().map(|(r, p)| format!("{}: {}", ast.rules[*r].identifier, get_insert(p.clone()))));
         for (t,r) in s.reduce.iter() {
             reduce.extend(r.iter().map(|r| format!("{:?}: {},{}", t, r.rule, r.reductend)));
         }
@@ -76,24 +77,69 @@ fn info(lr: &lr::LR, ast: &parser::GAst) {
         println!("{}", out.join(" | "));
     }
 }
+fn export(ast: &parser::GAst, automaton: &automaton::Automaton, lexer: &lexer::Lexer, options: &reverseparse::Options, rust: bool, output: Option<&str>) {
+    let (output, path) = if rust {
+        (reverseparse::export_rust(ast, automaton, lexer, options), output.unwrap_or("../parser/src/parse.rs"))
+    } else {
+        (reverseparse::export_cpp(ast, automaton, lexer, options), output.unwrap_or("../FO/parse.cpp"))
+    };
+    let mut file = match File::create(path) {
+        Err(e) => panic!("Could not open file {}: {:?}", path, e),
+        Ok(f) =>f
+    };
+
+    let _ = file.write_all(output.as_bytes());
+}
+
 fn main() {
-    // lexer::DFA::new(HashSet::from(["([abc])+!","[0-9]*abc!"].map(|s|{
+    // G [grammar] [--report dot|dot-automaton|html|json] [--load automaton.json] [--cst] [--table-lexer] [--rust] [--output path]
+    let mut grammar = "fo.g".to_string();
+    let mut report = None;
+    let mut load = None;
+    let mut rust = false;
+    let mut output = None;
+    let mut options = reverseparse::Options::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--report" => report = args.next(),
+            "--load" => load = args.next(),
+            "--cst" => options.cst = true,
+            "--table-lexer" => options.table_lexer = true,
+            "--rust" => rust = true,
+            "--output" => output = args.next(),
+            _ => grammar = arg,
+        }
+    }
 
-    //     let lex = lexer::Token::lexer(&s);
-    //     lexer::Parser::parse(lex)
-    // })));
-    let source = match read_to_string("fo.g") {
-        Ok(s) => s,
-        Err(e) => {
-            panic!("cannot read file!")
+    // skip grammar analysis and generate from a previous json dump
+    if let Some(path) = load {
+        let source = match read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) => panic!("cannot read file {}: {:?}", path, e)
+        };
+        match json::load(&source).and_then(|(ast, a)| lexer::Lexer::new(&a.terminals, &ast).map(|l| (ast, a, l))) {
+            Ok((ast, automaton, lexer)) => export(&ast, &automaton, &lexer, &options, rust, output.as_deref()),
+            Err(errors) => {
+                println!("Error occured!");
+                println!("{:?}", errors);
+            }
         }
+        return;
+    }
+
+    let source = match read_to_string(&grammar) {
+        Ok(s) => s,
+        Err(e) => panic!("cannot read file {}: {:?}", grammar, e)
     };
     let lex = parser::gTokens::lexer(source.as_str());
     let ast = match parser::parse(lex) {
         Ok(ast) => ast,
         Err(err) => panic!("Error while Parsing: {:?}", err),
     };
-    println!("Output: {:?}", ast);
+    if report.is_none() {
+        println!("Output: {:?}", ast);
+    }
 
     let lr = match lr::LR::new(&ast.rules) {
         Ok(lr)=>lr,
@@ -104,9 +150,24 @@ fn main() {
         }
     };
 
-    info(&lr, &ast);
+    match report.as_deref() {
+        None => info(&lr, &ast),
+        Some("dot") => {
+            print!("{}", report::lr_dot(&lr));
+            return;
+        }
+        Some("html") => {
+            print!("{}", report::lr_html(&lr, &source));
+            return;
+        }
+        Some("dot-automaton") | Some("json") => {}
+        Some(kind) => {
+            println!("Unknown report: {}", kind);
+            return;
+        }
+    }
 
-    let automaton = match automaton::Automaton::new(&lr) {
+    let mut automaton = match automaton::Automaton::new(&lr) {
         Ok(lr)=>lr,
         Err(errors) => {
             println!("Error occured!");
@@ -114,9 +175,49 @@ fn main() {
             return;
         }
     };
+    automaton.minimize();
+
+    let lexer = match lexer::Lexer::new(&automaton.terminals, &ast) {
+        Ok(lexer) => lexer,
+        Err(errors) => {
+            println!("Error occured!");
+            println!("{:?}", errors);
+            return;
+        }
+    };
+    let terminals: Vec<&lr::Token> = automaton.terminals.iter().chain(lexer.skip.iter()).collect();
+    // the dfa lexers only produce terminals the parser expects
+    let restricted = (!rust || options.table_lexer) && !lexer.indent;
+    for mode in &lexer.modes {
+        let context = if lexer.modes.len()>1 {format!(" in mode {}", mode.name)} else {String::new()};
+        for overlap in mode.overlaps() {
+            let (a, b) = overlap.tokens;
+            eprintln!("Overlap{}: {} and {} both match {:?}, lexed as {}", context, terminals[a], terminals[b], overlap.witness, terminals[overlap.winner]);
+        }
+        for (t, winners) in mode.shadowed() {
+            let winners: Vec<String> = winners.iter().map(|w| terminals[*w].to_string()).collect();
+            if restricted {
+                eprintln!("Warning{}: {} is only produced where {} is not expected", context, terminals[t], winners.join(", "));
+            } else {
+                eprintln!("Warning{}: {} is never produced, it is shadowed by {}", context, terminals[t], winners.join(", "));
+            }
+        }
+    }
+    match report.as_deref() {
+        Some("json") => {
+            print!("{}", json::dump(&ast, &lr, &automaton));
+            return;
+        }
+        Some(_) => {
+            print!("{}", report::automaton_dot(&automaton, &ast.rules));
+            return;
+        }
+        None => {}
+    }
     println!(
-        "terminals: {:?}, states: {:?}, reductors: {:?}",
+        "terminals: {:?}, nonterminals: {:?}, states: {:?}, reductors: {:?}",
         automaton.terminals.len(),
+        automaton.nonterminals.len(),
         automaton.states.len(),
         automaton.reductions.len()
     );
@@ -124,21 +225,14 @@ fn main() {
     for (i, term) in automaton.terminals.iter().enumerate() {
         println!("{}. {:?}", i, term);
     }
-    println!("");
+    println!();
     for (i, reductend) in automaton.reductions.iter().enumerate() {
         println!("{}. {:?}", i, reductend);
     }
-    println!("");
+    println!();
     for (i, state) in automaton.states.iter().enumerate() {
         println!("{}. {} {:?} {:?}", i, state.position.get_string(&ast.rules), state.lookahead, state.goto);
     }
 
-    let output = reverseparse::export_cpp(&automaton);
-    let mut file = match File::create("../FO/parse.cpp") {
-    // let mut file = match File::create("../parser/src/main.rs") {
-        Err(e) => panic!("Could not open file: {:?}", e),
-        Ok(f) =>f
-    };
-
-    let _ = file.write_all(output.as_bytes());
+    export(&ast, &automaton, &lexer, &options, rust, output.as_deref());
 }
@@ -1,5 +1,6 @@
 use logos::Logos;
 use std::rc::Rc;
+use serde::{Serialize, Deserialize};
 
 #[derive(Logos, Debug, PartialEq, PartialOrd)]
 #[logos(skip "//.*")]
@@ -46,6 +47,9 @@ pub enum gTokens {
     #[regex(r#"r"([^"\\]|\\.)*""#)]
     Regex,
 
+    #[regex(r#"i"([^"\\]|\\.)*""#)]
+    Insensitive,
+
     #[token("{")]
     CurleyOpen,
 
@@ -57,51 +61,131 @@ pub enum gTokens {
 
     #[token("*")]
     Star,
+
+    #[regex("%[a-zA-Z_]+")]
+    Directive,
+
+    #[token("<-")]
+    Pop,
 }
 
 #[derive(Debug)]
 pub enum Statement {
     Rule(Rule),
     Member(Member),
+    Mode(Mode),
+    Skip(Vec<Component0>),
+    Indent,
+    Prelude(Rc<str>),
+    Epilogue(Rc<str>),
+    Context(Rc<str>),
 }
 
-#[derive(Debug)]
+// $name: Type; a field the actions share, every parse starts with its default value
+// (Default in Rust, value initialised in C++) and the caller can read it after the parse
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Member {
     pub name: Rc<str>,
     pub member_type: Rc<str>,
 }
 
-#[derive(Debug)]
+// %mode name: "token" -> other r"regex" "end" <-;
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Mode {
+    pub name: Rc<str>,
+    pub tokens: Vec<ModeToken>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModeToken {
+    pub token: Component0,
+    pub transition: Transition,
+}
+
+// change of the lexer mode after the token was lexed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transition {
+    Stay,
+    Push(Rc<str>),
+    Pop,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Rule {
     pub identifier: Rc<str>,
     pub reductends: Reductends,
     pub export: Option<Rc<str>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Reductends {
     pub reductends: Vec<Reductend>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Reductend {
     pub components: Components,
     pub code: Option<Rc<str>>,
+    // ?{ ... } code returning a Result<T, ActionError> in rust or throwing in c++, errors abort the parse
+    #[serde(default)]
+    pub fallible: bool,
 }
+// the code of a reductend while parsing
 #[derive(Debug)]
+struct Action {
+    code: Rc<str>,
+    fallible: bool,
+}
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Components {
     pub components: Vec<Component>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Component0 {
     Rule(Rc<str>),
     Terminal(Rc<str>),
     Regex(Rc<str>),
+    // i"select" matches ASCII letters in either case
+    Insensitive(Rc<str>),
+    Synthetic(Synthetic),
     Token,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+// terminals produced by the lexer from the indentation with %indent,
+// referenced in rules by their reserved names INDENT, DEDENT and NEWLINE
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Synthetic {
+    Indent,
+    Dedent,
+    Newline,
+}
+
+impl std::fmt::Display for Synthetic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Synthetic::Indent => write!(f, "INDENT"),
+            Synthetic::Dedent => write!(f, "DEDENT"),
+            Synthetic::Newline => write!(f, "NEWLINE"),
+        }
+    }
+}
+
+fn synthetic(identifier: &str) -> Option<Synthetic> {
+    match identifier {
+        "INDENT" => Some(Synthetic::Indent),
+        "DEDENT" => Some(Synthetic::Dedent),
+        "NEWLINE" => Some(Synthetic::Newline),
+        _ => None,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Component {
     pub handle: Component0,
     pub var: Option<Rc<str>>,
@@ -114,10 +198,32 @@ pub struct Mods {
     option: Option<(Rc<str>, Rc<str>)>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct GAst {
     pub members: Vec<Member>,
     pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub modes: Vec<Mode>,
+    // %skip " " r"//[^\n]*";
+    // tokens dropped by the lexer, without any %skip declaration spaces are skipped
+    #[serde(default)]
+    pub skip: Option<Vec<Component0>>,
+    // %indent;
+    // the lexer produces INDENT, DEDENT and NEWLINE from line breaks in skipped input
+    #[serde(default)]
+    pub indent: bool,
+    // %prelude { use std::collections::HashMap; }
+    // code emitted verbatim in front of the generated parser
+    #[serde(default)]
+    pub prelude: Vec<Rc<str>>,
+    // %epilogue { ... }
+    // code emitted verbatim after the generated parser
+    #[serde(default)]
+    pub epilogue: Vec<Rc<str>>,
+    // %context SymbolTable;
+    // type of the value passed to parse, the actions reach it as ctx
+    #[serde(default)]
+    pub context: Option<Rc<str>>,
 }
 
 #[derive(Debug)]
@@ -144,6 +250,26 @@ fn statement_user_0(m: Member) -> Statement {
 fn statement_user_1(r: Rule) -> Statement {
     Statement::Rule(r)
 }
+fn statement_user_2(m: Mode) -> Statement {
+    Statement::Mode(m)
+}
+fn statement_user_3(s: Vec<Component0>) -> Statement {
+    Statement::Skip(s)
+}
+fn statement_user_4() -> Statement {
+    Statement::Indent
+}
+// the code block without its braces
+fn statement_user_5(code: Rc<str>, directive: Rc<str>) -> Statement {
+    let code: Rc<str> = code[1..code.len()-1].into();
+    match &*directive {
+        "%prelude" => Statement::Prelude(code),
+        _ => Statement::Epilogue(code),
+    }
+}
+fn statement_user_6(context: Rc<str>) -> Statement {
+    Statement::Context(context)
+}
 
 fn rule_user_0(reductends: Vec<Reductend>, identifier: Rc<str>) -> Rule {
     Rule {
@@ -183,12 +309,14 @@ fn reductent_user_0(components: Vec<Component>) -> Reductend {
     Reductend {
         components: Components { components },
         code: None,
+        fallible: false,
     }
 }
-fn reductent_user_1(code: Rc<str>, components: Vec<Component>) -> Reductend {
+fn reductent_user_1(action: Action, components: Vec<Component>) -> Reductend {
     Reductend {
         components: Components { components },
-        code: Some(code),
+        code: Some(action.code),
+        fallible: action.fallible,
     }
 }
 fn reductents_user_0(reductend: Reductend) -> Vec<Reductend> {
@@ -219,6 +347,12 @@ fn code_user_1(b: Rc<str>, a: Rc<str>) -> Rc<str> {
     let mut string = a.to_string() + &b;
     string.into()
 }
+fn action_user_0(code: Rc<str>) -> Action {
+    Action { code, fallible: false }
+}
+fn action_user_1(code: Rc<str>) -> Action {
+    Action { code, fallible: true }
+}
 fn type_user_0(a: Rc<str>) -> Rc<str> {
     a
 }
@@ -228,29 +362,63 @@ fn type_user_1(b: Rc<str>, a: Rc<str>) -> Rc<str> {
 }
 
 fn start_user_0(handle: Statement) -> GAst {
-    match handle {
-        Statement::Member(t) => GAst {
-            members: vec![t],
-            rules: Vec::new(),
-        },
-        Statement::Rule(t) => GAst {
-            members: Vec::new(),
-            rules: vec![t],
-        },
-    }
+    start_user_1(handle, GAst {
+        members: Vec::new(),
+        rules: Vec::new(),
+        modes: Vec::new(),
+        skip: None,
+        indent: false,
+        prelude: Vec::new(),
+        epilogue: Vec::new(),
+        context: None,
+    })
 }
 fn start_user_1(handle: Statement, mut stack: GAst) -> GAst {
     match handle {
         Statement::Member(t) => stack.members.push(t),
         Statement::Rule(t) => stack.rules.push(t),
+        Statement::Mode(t) => stack.modes.push(t),
+        Statement::Skip(t) => stack.skip.get_or_insert_with(Vec::new).extend(t),
+        Statement::Indent => stack.indent = true,
+        Statement::Prelude(t) => stack.prelude.push(t),
+        Statement::Epilogue(t) => stack.epilogue.push(t),
+        Statement::Context(t) => stack.context = Some(t),
     };
     stack
 }
 
+// INDENT, DEDENT and NEWLINE are rules unless %indent declares the synthetic tokens
+fn ast_user(mut ast: GAst) -> GAst {
+    if !ast.indent {
+        return ast;
+    }
+    for rule in ast.rules.iter_mut() {
+        for reductend in rule.reductends.reductends.iter_mut() {
+            for component in reductend.components.components.iter_mut() {
+                let token = match &component.handle {
+                    Component0::Rule(identifier) => synthetic(identifier),
+                    _ => None,
+                };
+                if let Some(token) = token {
+                    component.handle = Component0::Synthetic(token);
+                }
+            }
+        }
+    }
+    ast
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 enum States {
     Start,
     Member,
+    Mode,
+    ModeTokens,
+    Skip,
+    Indent,
+    Block,
+    ReduceBlock,
+    ReduceContext,
     Rule,
     Reductends,
     ReduceC0,
@@ -274,9 +442,12 @@ enum States {
     CodeR,
     CodeC,
     CodeE,
+    CodeA,
+    CodeF,
     PushR,
     PushT,
     PushX,
+    PushI,
     PushS,
     RAst0,
     RAst1,
@@ -287,12 +458,15 @@ enum Types {
     Ast(GAst),
     Statement(Statement),
     Member(Member),
+    Mode(Mode),
+    Skip(Vec<Component0>),
     Rule(Rule),
     Component(Component),
     Component0(Component0),
     VecComponent(Vec<Component>),
     VecReductend(Vec<Reductend>),
     Reductend(Reductend),
+    Action(Action),
     Token(Rc<str>),
 }
 // union Types
@@ -333,7 +507,7 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
         ($type:tt) => {
             match value_stack.pop() {
                 Some(Types::$type(val)) => val,
-                t @ _ => {
+                t => {
                     panic!("Wrong type! {:?}\nstack: {:?}", t, value_stack)
                 }
             }
@@ -346,13 +520,13 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
                 match $lex.next() {
                     $(Some(Ok($l)) => $r,)*
                     Some(Ok(gTokens::WhiteSpace)) => {continue;},
-                    t @ _ => {
+                    t => {
                         let span = $lex.span();
                         let source = $lex.source().get(..span.start).unwrap_or_else(||panic!("Source is Corrupted! {:?} {:?}", span.start, $lex.source().len()));
 
                         let new_lines: Vec<(usize, &str)> = source.match_indices("\n").collect();
                         let line = new_lines.len()+1;
-                        let start = new_lines.last().map_or(0, |s| s.0.clone()+1);
+                        let start = new_lines.last().map_or(0, |s| s.0+1);
                         let offset = span.start-start;
                         let r = Err(GError{expected: vec![$($l,)*], found: t, text: String::from($lex.source().get(start..span.end).unwrap()), line, offset, len: span.end-span.start});
                         panic!("Error: {:?}\nstack: {:?}", r, value_stack);
@@ -372,17 +546,34 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
                     Some(Ok(gTokens::Var)) => {
                         state = States::Member;
                     }
+                    Some(Ok(gTokens::Directive)) => match lex.slice() {
+                        "%mode" => state = States::Mode,
+                        "%skip" => {
+                            reduce!(Skip, Vec::new());
+                            state = States::Skip;
+                        }
+                        "%indent" => state = States::Indent,
+                        "%context" => {
+                            state_stack.push(States::ReduceContext);
+                            state = States::Export;
+                        }
+                        "%prelude" | "%epilogue" => {
+                            push_lex!(lex);
+                            state = States::Block;
+                        }
+                        d => panic!("Error: Unknown directive {}\nstack: {:?}", d, value_stack),
+                    },
                     Some(Ok(gTokens::Identifier)) => {
                         push_lex!(lex);
                         state = States::Rule;
                     }
                     None => {
-                        return Ok(pop_val!(Ast));
+                        return Ok(ast_user(pop_val!(Ast)));
                     }
                     Some(Ok(gTokens::WhiteSpace)) => {
                         continue;
                     }
-                    t @ _ => {
+                    t => {
                         let span = lex.span();
                         let source = lex.source().get(..span.start).unwrap_or_else(|| {
                             panic!(
@@ -394,10 +585,10 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
 
                         let new_lines: Vec<(usize, &str)> = source.match_indices("\n").collect();
                         let line = new_lines.len() + 1;
-                        let start = new_lines.last().map_or(0, |s| s.0.clone() + 1);
+                        let start = new_lines.last().map_or(0, |s| s.0 + 1);
                         let offset = span.start - start;
                         let r = Err(GError {
-                            expected: vec![gTokens::Var, gTokens::Identifier],
+                            expected: vec![gTokens::Var, gTokens::Directive, gTokens::Identifier],
                             found: t,
                             text: String::from(lex.source().get(start..span.end).unwrap()),
                             line,
@@ -432,6 +623,103 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
                 reduce!(Statement, stmt_user(pop_val!(Statement)));
                 state = state_stack.pop().unwrap();
             }
+            States::Mode => {
+                match_next!(lex, gTokens::Identifier, {
+                    reduce!(Mode, Mode { name: lex.slice().into(), tokens: Vec::new() })
+                });
+                match_next!(lex, gTokens::Colon, {});
+                state = States::ModeTokens;
+            }
+            States::ModeTokens => {
+                let mut mode = pop_val!(Mode);
+                match_next!(
+                    lex,
+                    gTokens::Terminal,
+                    {
+                        let token = Component0::Terminal(lex.slice().into());
+                        mode.tokens.push(ModeToken { token, transition: Transition::Stay });
+                    },
+                    gTokens::Regex,
+                    {
+                        let token = Component0::Regex(lex.slice().into());
+                        mode.tokens.push(ModeToken { token, transition: Transition::Stay });
+                    },
+                    gTokens::Insensitive,
+                    {
+                        let token = Component0::Insensitive(lex.slice().into());
+                        mode.tokens.push(ModeToken { token, transition: Transition::Stay });
+                    },
+                    gTokens::Arrow,
+                    {
+                        match_next!(lex, gTokens::Identifier, {});
+                        if let Some(last) = mode.tokens.last_mut() {
+                            last.transition = Transition::Push(lex.slice().into());
+                        }
+                    },
+                    gTokens::Pop,
+                    {
+                        if let Some(last) = mode.tokens.last_mut() {
+                            last.transition = Transition::Pop;
+                        }
+                    },
+                    gTokens::Semicolon,
+                    {
+                        reduce!(Statement, statement_user_2(mode));
+                        reduce!(Statement, stmt_user(pop_val!(Statement)));
+                        state = state_stack.pop().unwrap();
+                        continue 'a;
+                    }
+                );
+                reduce!(Mode, mode);
+            }
+            States::Indent => {
+                match_next!(lex, gTokens::Semicolon, {});
+                reduce!(Statement, statement_user_4());
+                reduce!(Statement, stmt_user(pop_val!(Statement)));
+                state = state_stack.pop().unwrap();
+            }
+            States::Block => {
+                match_next!(lex, gTokens::CurleyOpen, {});
+                state_stack.push(States::ReduceBlock);
+                state_stack.push(States::CodeR);
+                state = States::Code;
+            }
+            States::ReduceBlock => {
+                reduce!(Statement, statement_user_5(pop_val!(Token), pop_val!(Token)));
+                reduce!(Statement, stmt_user(pop_val!(Statement)));
+                state = state_stack.pop().unwrap();
+            }
+            States::ReduceContext => {
+                reduce!(Statement, statement_user_6(pop_val!(Token)));
+                reduce!(Statement, stmt_user(pop_val!(Statement)));
+                state = state_stack.pop().unwrap();
+            }
+            States::Skip => {
+                let mut skip = pop_val!(Skip);
+                match_next!(
+                    lex,
+                    gTokens::Terminal,
+                    {
+                        skip.push(Component0::Terminal(lex.slice().into()));
+                    },
+                    gTokens::Regex,
+                    {
+                        skip.push(Component0::Regex(lex.slice().into()));
+                    },
+                    gTokens::Insensitive,
+                    {
+                        skip.push(Component0::Insensitive(lex.slice().into()));
+                    },
+                    gTokens::Semicolon,
+                    {
+                        reduce!(Statement, statement_user_3(skip));
+                        reduce!(Statement, stmt_user(pop_val!(Statement)));
+                        state = state_stack.pop().unwrap();
+                        continue 'a;
+                    }
+                );
+                reduce!(Skip, skip);
+            }
             States::Rule => {
                 match_next!(lex, gTokens::Colon, {});
                 state = States::RStart;
@@ -478,7 +766,7 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
             States::ReduceRC => {
                 reduce!(
                     Reductend,
-                    reductent_user_1(pop_val!(Token), pop_val!(VecComponent))
+                    reductent_user_1(pop_val!(Action), pop_val!(VecComponent))
                 );
                 state = state_stack.pop().unwrap();
             }
@@ -502,6 +790,10 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
                 reduce!(Component0, Component0::Regex(lex.slice().into()));
                 state = state_stack.pop().unwrap();
             }
+            States::PushI => {
+                reduce!(Component0, Component0::Insensitive(lex.slice().into()));
+                state = state_stack.pop().unwrap();
+            }
             States::PushS => {
                 reduce!(Component0, Component0::Token);
                 state = state_stack.pop().unwrap();
@@ -540,6 +832,13 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
                         state_stack.push(States::PushX);
                         state = c.clone();
                     },
+                    gTokens::Insensitive,
+                    {
+                        reduce!(Component, component_user_0(pop_val!(Component0)));
+                        state_stack.push(States::Reductends);
+                        state_stack.push(States::PushI);
+                        state = c.clone();
+                    },
                     gTokens::Star,
                     {
                         reduce!(Component, component_user_0(pop_val!(Component0)));
@@ -551,6 +850,17 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
                     {
                         reduce!(Component, component_user_0(pop_val!(Component0)));
                         state_stack.push(States::CodeE);
+                        state_stack.push(States::CodeA);
+                        state_stack.push(States::CodeR);
+                        state_stack.push(States::Code);
+                        state = c.clone();
+                    },
+                    gTokens::Ternary,
+                    {
+                        match_next!(lex, gTokens::CurleyOpen, {});
+                        reduce!(Component, component_user_0(pop_val!(Component0)));
+                        state_stack.push(States::CodeE);
+                        state_stack.push(States::CodeF);
                         state_stack.push(States::CodeR);
                         state_stack.push(States::Code);
                         state = c.clone();
@@ -617,7 +927,7 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
 
                     let new_lines: Vec<(usize, &str)> = source.match_indices("\n").collect();
                     let line = new_lines.len() + 1;
-                    let start = new_lines.last().map_or(0, |s| s.0.clone() + 1);
+                    let start = new_lines.last().map_or(0, |s| s.0 + 1);
                     let offset = span.start - start;
                     let r = Err(GError {
                         expected: vec![],
@@ -656,7 +966,7 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
 
                     let new_lines: Vec<(usize, &str)> = source.match_indices("\n").collect();
                     let line = new_lines.len() + 1;
-                    let start = new_lines.last().map_or(0, |s| s.0.clone() + 1);
+                    let start = new_lines.last().map_or(0, |s| s.0 + 1);
                     let offset = span.start - start;
                     let r = Err(GError {
                         expected: vec![],
@@ -683,6 +993,8 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
                     { reduce!(Component0, Component0::Terminal(lex.slice().into())) },
                     gTokens::Regex,
                     { reduce!(Component0, Component0::Regex(lex.slice().into())) },
+                    gTokens::Insensitive,
+                    { reduce!(Component0, Component0::Insensitive(lex.slice().into())) },
                     gTokens::Star,
                     { reduce!(Component0, Component0::Token) }
                 );
@@ -697,11 +1009,23 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
                     { reduce!(Component0, Component0::Terminal(lex.slice().into())) },
                     gTokens::Regex,
                     { reduce!(Component0, Component0::Regex(lex.slice().into())) },
+                    gTokens::Insensitive,
+                    { reduce!(Component0, Component0::Insensitive(lex.slice().into())) },
                     gTokens::Star,
                     { reduce!(Component0, Component0::Token) },
                     gTokens::CurleyOpen,
                     {
                         state_stack.push(States::CodeE);
+                        state_stack.push(States::CodeA);
+                        state_stack.push(States::CodeR);
+                        state = States::Code;
+                        continue 'a;
+                    },
+                    gTokens::Ternary,
+                    {
+                        match_next!(lex, gTokens::CurleyOpen, {});
+                        state_stack.push(States::CodeE);
+                        state_stack.push(States::CodeF);
                         state_stack.push(States::CodeR);
                         state = States::Code;
                         continue 'a;
@@ -745,6 +1069,14 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
                 reduce!(Token, code_user_0(pop_val!(Token)));
                 state = state_stack.pop().unwrap();
             }
+            States::CodeA => {
+                reduce!(Action, action_user_0(pop_val!(Token)));
+                state = state_stack.pop().unwrap();
+            }
+            States::CodeF => {
+                reduce!(Action, action_user_1(pop_val!(Token)));
+                state = state_stack.pop().unwrap();
+            }
             States::CodeC => {
                 reduce!(Token, code_user_1(pop_val!(Token), pop_val!(Token)));
                 state = state_stack.pop().unwrap();
@@ -770,7 +1102,7 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
 
                     let new_lines: Vec<(usize, &str)> = source.match_indices("\n").collect();
                     let line = new_lines.len() + 1;
-                    let start = new_lines.last().map_or(0, |s| s.0.clone() + 1);
+                    let start = new_lines.last().map_or(0, |s| s.0 + 1);
                     let offset = span.start - start;
                     let r = Err(GError {
                         expected: vec![],
@@ -810,7 +1142,7 @@ pub fn parse(mut lex: logos::Lexer<gTokens>) -> Result<GAst, GError> {
 
                     let new_lines: Vec<(usize, &str)> = source.match_indices("\n").collect();
                     let line = new_lines.len() + 1;
-                    let start = new_lines.last().map_or(0, |s| s.0.clone() + 1);
+                    let start = new_lines.last().map_or(0, |s| s.0 + 1);
                     let offset = span.start - start;
                     let r = Err(GError {
                         expected: vec![],
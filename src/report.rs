@@ -0,0 +1,238 @@
+use crate::{lr, automaton::{self, Action}, parser};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+fn dot_string(s: &str) -> String {
+    s.replace("\\", "\\\\").replace("\"", "\\\"")
+}
+
+fn tokens_string<'a>(tokens: impl IntoIterator<Item=&'a lr::Token>) -> String {
+    tokens.into_iter().map(|t| t.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+// graph of the LR states, conflicting states are drawn red
+pub fn lr_dot(lr: &lr::LR) -> String {
+    let order = lr.states();
+    let index: HashMap<&lr::StateHead, usize> = order.iter().enumerate().map(|(i, h)| (*h, i)).collect();
+
+    let mut content = String::from("digraph LR {\n\trankdir=LR;\n\tnode [shape=box, fontname=\"monospace\"];\n");
+    let mut edges = String::new();
+
+    for (i, head) in order.iter().enumerate() {
+        let state = &lr.state_map[*head];
+
+        let mut label = format!("State {}\\l", i);
+        for (position, import) in head.iter() {
+            label += dot_string(&format!("{} [{}]", position.get_string(lr.rules), tokens_string(import))).as_str();
+            label += "\\l";
+        }
+
+        // group reduce actions by reductend
+        let mut reduce: BTreeMap<&lr::ReductendPosition, BTreeSet<&lr::Token>> = BTreeMap::new();
+        for (token, reductends) in state.reduce.iter() {
+            for r in reductends {
+                reduce.entry(r).or_default().insert(token);
+            }
+        }
+        for (r, tokens) in reduce {
+            let rule = &lr.rules[r.rule].identifier;
+            label += dot_string(&format!("reduce {}.{} on {}", rule, r.reductend, tokens_string(tokens))).as_str();
+            label += "\\l";
+        }
+
+        let conflicts = state.conflicts();
+        if conflicts.is_empty() {
+            content += format!("\ts{} [label=\"{}\"];\n", i, label).as_str();
+        } else {
+            label += dot_string(&format!("conflict on {}", tokens_string(&conflicts))).as_str();
+            label += "\\l";
+            content += format!("\ts{} [label=\"{}\", color=red, fontcolor=red];\n", i, label).as_str();
+        }
+
+        let mut next: Vec<_> = state.next.iter().collect();
+        next.sort();
+        for (token, target) in next {
+            edges += format!("\ts{} -> s{} [label=\"{}\"];\n", i, index[target], dot_string(&token.to_string())).as_str();
+        }
+        let mut goto: Vec<_> = state.goto.iter().collect();
+        goto.sort();
+        for (rule, target) in goto {
+            edges += format!("\ts{} -> s{} [label=\"{}\", style=dashed];\n", i, index[target], dot_string(&lr.rules[*rule].identifier)).as_str();
+        }
+    }
+
+    content += edges.as_str();
+    content += "}\n";
+    content
+}
+
+// graph of the baked automaton
+pub fn automaton_dot(automaton: &automaton::Automaton, rules: &Vec<parser::Rule>) -> String {
+    let mut content = String::from("digraph Automaton {\n\trankdir=LR;\n\tnode [shape=box, fontname=\"monospace\"];\n");
+    let mut edges = String::new();
+
+    for (i, state) in automaton.states.iter().enumerate() {
+        let mut label = format!("State {}\\l", i);
+        for position in state.position.iter() {
+            label += dot_string(&position.get_string(rules)).as_str();
+            label += "\\l";
+        }
+
+        let mut reduce: BTreeMap<usize, Vec<&lr::Token>> = BTreeMap::new();
+        for t in state.expected() {
+            match state.action(t) {
+                Action::Shift(target) => {
+                    edges += format!("\ts{} -> s{} [label=\"{}\"];\n", i, target, dot_string(&automaton.terminals[t].to_string())).as_str();
+                }
+                Action::Reduce(r) => reduce.entry(r).or_default().push(&automaton.terminals[t]),
+                Action::Accept => label += "accept on EOF\\l",
+                Action::Error => {}
+            }
+        }
+        for (r, tokens) in reduce {
+            let nonterminal = &automaton.nonterminals[automaton.reductions[r].nonterminal];
+            label += dot_string(&format!("reduce {} ({}) on {}", nonterminal, r, tokens_string(tokens))).as_str();
+            label += "\\l";
+        }
+        content += format!("\ts{} [label=\"{}\"];\n", i, label).as_str();
+
+        let mut goto: Vec<_> = state.goto.iter().collect();
+        goto.sort();
+        for (nonterminal, target) in goto {
+            edges += format!("\ts{} -> s{} [label=\"{}\", style=dashed];\n", i, target, dot_string(&automaton.nonterminals[*nonterminal])).as_str();
+        }
+    }
+
+    content += edges.as_str();
+    content += "}\n";
+    content
+}
+
+fn html_string(s: &str) -> String {
+    s.replace("&", "&amp;").replace("<", "&lt;").replace(">", "&gt;").replace("\"", "&quot;")
+}
+
+fn state_link(i: usize) -> String {
+    format!("<a href=\"#s{}\">{}</a>", i, i)
+}
+
+// describe all actions of an LR state on one token
+fn lr_actions(lr: &lr::LR, state: &lr::State, index: &HashMap<&lr::StateHead, usize>, token: &lr::Token) -> Vec<String> {
+    let mut actions = Vec::new();
+    if let Some(target) = state.next.get(token) {
+        actions.push(format!("shift {}", state_link(index[target])));
+    }
+    for r in state.reduce.get(token).into_iter().flatten() {
+        actions.push(format!("reduce {}.{}", html_string(&lr.rules[r.rule].identifier), r.reductend));
+    }
+    actions
+}
+
+// standalone page listing conflicts, states and the annotated grammar source
+pub fn lr_html(lr: &lr::LR, source: &str) -> String {
+    let order = lr.states();
+    let index: HashMap<&lr::StateHead, usize> = order.iter().enumerate().map(|(i, h)| (*h, i)).collect();
+
+    let mut content = String::from(r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>MLCC State Report</title>
+<style>
+body { font-family: sans-serif; }
+pre, td { font-family: monospace; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+td, th { border: 1px solid #ccc; padding: 2px 8px; text-align: left; vertical-align: top; }
+.conflict { color: #c00; }
+.states { color: #888; }
+</style>
+</head>
+<body>
+"#);
+
+    // Conflicts
+    content += "<h1>Conflicts</h1>\n";
+    let mut conflicts = String::new();
+    for (i, head) in order.iter().enumerate() {
+        let state = &lr.state_map[*head];
+        for token in state.conflicts() {
+            let actions = lr_actions(lr, state, &index, &token);
+            conflicts += format!("<li>State {} on {}: {}</li>\n", state_link(i), html_string(&token.to_string()), actions.join(", ")).as_str();
+        }
+    }
+    if conflicts.is_empty() {
+        content += "<p>None</p>\n";
+    } else {
+        content += format!("<ul class=\"conflict\">\n{}</ul>\n", conflicts).as_str();
+    }
+
+    // Grammar, every rule definition is followed by the states containing its items
+    let mut rule_states: HashMap<usize, BTreeSet<usize>> = HashMap::new();
+    for (i, head) in order.iter().enumerate() {
+        for position in head.keys() {
+            rule_states.entry(position.rule).or_default().insert(i);
+        }
+    }
+    content += "<h1>Grammar</h1>\n<pre>\n";
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+        let name_len = trimmed.find(|c: char| !(c.is_alphanumeric() || c=='_')).unwrap_or(trimmed.len());
+        let (name, rest) = trimmed.split_at(name_len);
+        let rule = lr.rules.iter().position(|r| &*r.identifier==name);
+        match rule {
+            Some(rule) if name_len>0 && rest.trim_start().starts_with(':') => {
+                let states: Vec<_> = rule_states.get(&rule).into_iter().flatten().map(|i| state_link(*i)).collect();
+                content += format!("{}<b id=\"rule-{}\">{}</b>{}  <span class=\"states\">// states: {}</span>\n",
+                                   &line[..line.len()-trimmed.len()], html_string(name), html_string(name), html_string(rest), states.join(" ")).as_str();
+            }
+            _ => {
+                content += html_string(line).as_str();
+                content += "\n";
+            }
+        }
+    }
+    content += "</pre>\n";
+
+    // States
+    content += "<h1>States</h1>\n";
+    for (i, head) in order.iter().enumerate() {
+        let state = &lr.state_map[*head];
+        let conflicts = state.conflicts();
+
+        if conflicts.is_empty() {
+            content += format!("<h2 id=\"s{}\">State {}</h2>\n", i, i).as_str();
+        } else {
+            content += format!("<h2 id=\"s{}\" class=\"conflict\">State {} (conflict)</h2>\n", i, i).as_str();
+        }
+
+        content += "<table>\n<tr><th>Item</th><th>Lookahead</th></tr>\n";
+        for (position, import) in head.iter() {
+            content += format!("<tr><td><a href=\"#rule-{}\">{}</a></td><td>{}</td></tr>\n",
+                               html_string(&lr.rules[position.rule].identifier),
+                               html_string(&position.get_string(lr.rules)),
+                               html_string(&tokens_string(import))).as_str();
+        }
+        content += "</table>\n";
+
+        let mut tokens: BTreeSet<&lr::Token> = state.next.keys().collect();
+        tokens.extend(state.reduce.keys());
+        content += "<table>\n<tr><th>On</th><th>Action</th></tr>\n";
+        for token in tokens {
+            let class = if conflicts.contains(token) {" class=\"conflict\""} else {""};
+            content += format!("<tr{}><td>{}</td><td>{}</td></tr>\n", class,
+                               html_string(&token.to_string()),
+                               lr_actions(lr, state, &index, token).join(", ")).as_str();
+        }
+        let mut goto: Vec<_> = state.goto.iter().collect();
+        goto.sort();
+        for (rule, target) in goto {
+            content += format!("<tr><td><a href=\"#rule-{}\">{}</a></td><td>goto {}</td></tr>\n",
+                               html_string(&lr.rules[*rule].identifier),
+                               html_string(&lr.rules[*rule].identifier),
+                               state_link(index[target])).as_str();
+        }
+        content += "</table>\n";
+    }
+
+    content += "</body>\n</html>\n";
+    content
+}
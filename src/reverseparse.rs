@@ -1,7 +1,63 @@
-use crate::{lr, automaton::{self, Action}, };
+use crate::{lr, automaton::{self, Action}, lexer::{self, Lexer, ModeChange}, parser};
 use std::collections::HashMap;
 
-pub fn export_rust(automaton: &automaton::Automaton) -> String {
+// action table encoding shared by all backends
+// 0: error, 1: accept, >1: shift to state n-2, <0: reduce with reduction -n-1
+fn encode_action(action: &Action) -> isize {
+    match action {
+        Action::Error => 0,
+        Action::Accept => 1,
+        Action::Reduce(i) => - (*i as isize) -1,
+        Action::Shift(i) => *i as isize +2
+    }
+}
+
+fn c_string(s: &str) -> String {
+    format!("\"{}\"", s.replace("\\", "\\\\").replace("\"", "\\\""))
+}
+
+// mode change table encoding shared by all backends
+// 0: stay, -1: pop, >0: push mode n-1
+fn encode_transition(change: &ModeChange) -> isize {
+    match change {
+        ModeChange::Stay => 0,
+        ModeChange::Pop => -1,
+        ModeChange::Push(m) => *m as isize +1
+    }
+}
+
+// code generation options shared by all backends
+#[derive(Debug, Default)]
+pub struct Options {
+    // build a lossless concrete syntax tree instead of running the actions
+    pub cst: bool,
+    // generate a table driven lexer instead of depending on logos
+    pub table_lexer: bool,
+}
+
+// action code with every $member replaced by its field behind the prefix
+fn members_code(code: &str, members: &[parser::Member], prefix: &str) -> String {
+    let mut out = String::new();
+    let mut rest = code;
+    while let Some(i) = rest.find('$') {
+        out += &rest[..i];
+        rest = &rest[i+1..];
+        let len = rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(rest.len());
+        if members.iter().any(|m| *m.name == rest[..len]) {
+            out += prefix;
+        } else {
+            out.push('$');
+        }
+    }
+    out + rest
+}
+
+// name of the generated logos token enum of a mode
+fn mode_token(mode: usize) -> String {
+    if mode==0 {"Token".to_string()} else {format!("Token{}", mode)}
+}
+
+pub fn export_rust(ast: &parser::GAst, automaton: &automaton::Automaton, lexer: &Lexer, options: &Options) -> String {
 //     let mut content = String::from(r#"
 // use std::rc::Rc;
 // #[derive(Debug)]
@@ -83,62 +139,227 @@ pub fn export_rust(automaton: &automaton::Automaton) -> String {
 // }
 // "#);
 
-    let mut content = String::from(r#"
+    let mut content = String::new();
+    for p in ast.prelude.iter() {
+        content += p;
+        content += "\n";
+    }
+    content += r#"// error of a ?{ ... } action, ? converts other errors into it
+pub type ActionError = Box<dyn std::error::Error + Send + Sync>;
+
+// error of the generated parser, lines and columns start at 1
+#[derive(Debug)]
+pub enum ParseError {
+	// input no terminal of the current lexer mode matches
+	UnexpectedInput{line: usize, column: usize, text: String},
+	// terminal without an action in the current state
+	UnexpectedToken{line: usize, column: usize, token: &'static str, text: String, expected: Vec<&'static str>},
+	// dedent to an indentation no enclosing block has
+	Indentation{line: usize, column: usize},
+	// error returned by an action
+	Action{line: usize, column: usize, error: ActionError},
+}
+
+impl std::fmt::Display for ParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			ParseError::UnexpectedInput{line, column, text} => write!(f, "{}:{}: Unexpected Input {:?}", line, column, text),
+			ParseError::UnexpectedToken{line, column, token, text, expected} =>
+				write!(f, "{}:{}: Unexpected Token {} ({:?}), expected one of {}", line, column, token, text, expected.join(", ")),
+			ParseError::Indentation{line, column} => write!(f, "{}:{}: Dedent to an unknown indentation", line, column),
+			ParseError::Action{line, column, error} => write!(f, "{}:{}: {}", line, column, error),
+		}
+	}
+}
+
+impl std::error::Error for ParseError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ParseError::Action{error, ..} => Some(error.as_ref()),
+			_ => None,
+		}
+	}
+}
+
+"#;
+
+    if options.table_lexer {
+        content += table_lexer(automaton, lexer).as_str();
+    } else {
+        //generate Regex, one logos lexer per mode with the terminal indices as discriminants
+        //skipped tokens follow the terminals and are dropped by the parser
+        content += "use logos::Logos;\n";
+        for (m, mode) in lexer.modes.iter().enumerate() {
+            content += format!("// mode {}\n", mode.name).as_str();
+            content += "#[derive(Logos, Debug, PartialEq, PartialOrd)]\n";
+            content += format!("pub enum {} {{\n", mode_token(m)).as_str();
+            content += "\tEOF = 0,\n";
+            for (i,t) in automaton.terminals.iter().chain(lexer.skip.iter()).enumerate() {
+                // earlier tokens of the mode win ties of the longest match
+                let priority = match mode.tokens.iter().position(|t| *t == i) {
+                    Some(p) => mode.tokens.len() - p,
+                    None => continue
+                };
+                match t {
+                    lr::Token::EOF | lr::Token::Synthetic(_) => {}
+                    lr::Token::Regex(r) => {
+                        // mlcc regexes differ from the regex crate, render the parsed form
+                        let regex = lexer::regex_syntax(&lexer::parse(&r[2..r.len()-1]).unwrap());
+                        content+= format!("\t#[regex(r#\"{}\"#, priority = {})]\n", regex, priority).as_str();
+                        content+= format!("\tL{} = {},\n", i, i).as_str();
+                    }
+                    lr::Token::Terminal(t) => {
+                        content+= format!("\t#[token({:?}, priority = {})]\n", lexer::unescape(&t[1..t.len()-1]), priority).as_str();
+                        content+= format!("\tL{} = {},\n", i, i).as_str();
+                    }
+                    lr::Token::Insensitive(t) => {
+                        content+= format!("\t#[token({:?}, priority = {}, ignore(ascii_case))]\n", lexer::unescape(&t[2..t.len()-1]), priority).as_str();
+                        content+= format!("\tL{} = {},\n", i, i).as_str();
+                    }
+                }
+            }
+            content += "}\n\n";
+        }
+
+        // the lexer of the current mode, switching modes keeps the position
+        content += "enum Lexers<'a> {\n";
+        for m in 0..lexer.modes.len() {
+            content += format!("\tM{}(logos::Lexer<'a, {}>),\n", m, mode_token(m)).as_str();
+        }
+        content += "}\n\n";
+        let each = |f: &str| (0..lexer.modes.len()).map(|m| format!("\t\t\tLexers::M{}(l) => {},\n", m, f)).collect::<String>();
+        content += format!(r#"impl<'a> Lexers<'a> {{
+	fn next(&mut self) -> Option<Result<usize, ()>> {{
+		match self {{
+{}		}}
+	}}
+
+	fn slice(&self) -> &'a str {{
+		match self {{
+{}		}}
+	}}
+
+	fn span(&self) -> std::ops::Range<usize> {{
+		match self {{
+{}		}}
+	}}
+
+	fn source(&self) -> &'a str {{
+		match self {{
+{}		}}
+	}}
+
+	fn switch(&mut self, mode: usize) {{
+		*self = match std::mem::replace(self, Lexers::M0(Token::lexer(""))) {{
+{}		}};
+	}}
+
+	fn morph<T: Logos<'a, Source = str, Extras = ()>>(l: logos::Lexer<'a, T>, mode: usize) -> Self {{
+		match mode {{
+{}			_ => unreachable!()
+		}}
+	}}
+}}
+
+"#, each("l.next().map(|t| t.map(|t| t as usize))"), each("l.slice()"), each("l.span()"), each("l.source()"),
+            each("Self::morph(l, mode)"),
+            (0..lexer.modes.len()).map(|m| format!("\t\t\t{} => Lexers::M{}(l.morph()),\n", m, m)).collect::<String>()).as_str();
+    }
+
+    if options.cst {
+        content += r#"// skipped input in front of a token
 #[derive(Debug, Clone)]
-enum Term{
-    Function(String, Vec<Term>),
-    Constant(String)
+pub struct Trivia<'a> {
+	pub token: usize,
+	pub text: &'a str,
+	pub range: std::ops::Range<usize>,
 }
+
+// lossless concrete syntax tree, writing it reproduces the input
+// the root node ends with the eof token holding the trailing trivia
 #[derive(Debug, Clone)]
-enum Formula{
-    IF(Box<Formula>, Box<Formula>),
-    IFF(Box<Formula>, Box<Formula>),
-    AND(Box<Formula>, Box<Formula>),
-    OR(Box<Formula>, Box<Formula>),
-    Not(Box<Formula>),
-    Rel(String, Vec<Term>),
-    Eq(Term, Term),
-    Forall(Box<Formula>, String),
-    Exists(Box<Formula>, String),
+pub enum Cst<'a> {
+	Token {
+		terminal: usize,
+		text: &'a str,
+		range: std::ops::Range<usize>,
+		trivia: Vec<Trivia<'a>>,
+	},
+	Node {
+		nonterminal: usize,
+		range: std::ops::Range<usize>,
+		children: Vec<Cst<'a>>,
+	},
 }
-"#);
 
-    //generate Regex
-    content += "use logos::Logos;\n";
-    content += "#[derive(Logos, Debug, PartialEq, PartialOrd)]\n";
-    content += r#"#[logos(skip " ")]"#;
-    content += "pub enum Token {\n";
-    for (i,t) in automaton.terminals.iter().enumerate() {
-        match t {
-            lr::Token::EOF => {
-                content += "\tEOF,\n";
-            }
-            lr::Token::Regex(r) => {
-                let mut string = r.to_string();
-                string.remove(0);
-                content+= format!("\t#[regex(r#{}#)]\n", string).as_str();
-                content+= format!("\tL{},\n", i).as_str();
-            }
-            lr::Token::Terminal(t) => {
-                content+= format!("\t#[token(r#{}#)]\n", t).as_str();
-                content+= format!("\tL{},\n", i).as_str();
-            }
+impl<'a> Cst<'a> {
+	// byte range without leading trivia
+	pub fn range(&self) -> std::ops::Range<usize> {
+		match self {
+			Cst::Token{range, ..} | Cst::Node{range, ..} => range.clone(),
+		}
+	}
+
+	pub fn write(&self, out: &mut String) {
+		match self {
+			Cst::Token{text, trivia, ..} => {
+				for t in trivia {
+					*out += t.text;
+				}
+				*out += text;
+			}
+			Cst::Node{children, ..} => for c in children {
+				c.write(out);
+			}
+		}
+	}
+}
+
+"#;
+    }
+
+    let members = !ast.members.is_empty() && !options.cst;
+    // errors of ?{ } actions need the start of the reduced values
+    let fallible = !options.cst && automaton.reductions.iter().any(|r| r.task.as_ref().is_some_and(|t| t.fallible));
+    // the context is only used by the actions
+    let context = ast.context.as_ref().filter(|_| !options.cst);
+    if members {
+        // every parse starts with default members and returns them next to the export
+        content += "// $member fields the actions share during a parse\n#[derive(Debug, Default)]\npub struct Members {\n";
+        for m in ast.members.iter() {
+            content += format!("\tpub {}: {},\n", m.name, m.member_type).as_str();
         }
+        content += "}\n\n";
     }
-    content += "}\n\n";
 
     content += "struct Parser<'a> {\n";
     content += "\tparse_stack: Vec<Types<'a>>,\n";
     content += "\tstate_stack: Vec<usize>,\n";
-    content += "\tlexer: logos::Lexer<'a, Token>\n";
+    content += if options.table_lexer {"\tlexer: Lexer<'a>,\n"} else {"\tlexer: Lexers<'a>,\n"};
+    if options.cst {
+        content += "\ttrivia: Vec<Trivia<'a>>,\n";
+    }
+    if lexer.indent {
+        // synthetic terminals and the lexed token after them in reverse order
+        content += "\tpending: Vec<usize>,\n";
+        content += "\tindents: Vec<usize>,\n";
+        content += "\tline_end: Option<usize>,\n";
+    }
+    if members {
+        content += "\tmembers: Members,\n";
+    }
+    if fallible {
+        // start offset of every value on the parse stack
+        content += "\tpositions: Vec<usize>,\n";
+    }
+    content += "\tmodes: Vec<usize>\n";
     content += "}\n\n";
 
     content += r#"macro_rules! pop{
 	($self:ident, $t:ident) => {
 		match $self.parse_stack.pop().unwrap() {
 			Types::$t(t) =>t,
-			o@_ => {println!("Expected: {:?} got {:?}", stringify!($t), o);
-            unreachable!()}
+			_ => unreachable!()
 		}
 	}
 }"#;
@@ -156,23 +377,16 @@ enum Formula{
 
 
     let terminals_len = automaton.terminals.len();
-    let reductions_len = automaton.reductions.len();
+    let nonterminals_len = automaton.nonterminals.len();
 
     let mut actions = format!("\tconst ACTION: [ [isize; {}]; {}] = [\n", terminals_len, automaton.states.len());
-    let mut gotos = format!("\tconst GOTO: [ [usize; {}]; {}] = [\n", reductions_len, automaton.states.len());
+    let mut gotos = format!("\tconst GOTO: [ [usize; {}]; {}] = [\n", nonterminals_len, automaton.states.len());
 
     for state in automaton.states.iter() {
-        let mut array = vec![0; terminals_len];
-        for (i,a) in state.lookahead.iter() {
-            array[*i] = match a {
-                Action::Halt => 0,
-                Action::Reduce(i) => - (*i as isize) -1,
-                Action::Shift(i) => *i as isize +1
-            }
-        }
+        let array: Vec<_> = (0..terminals_len).map(|t| encode_action(&state.action(t))).collect();
         actions += format!("\t\t{:?}, \n", array).as_str();
 
-        let mut array = vec![0; reductions_len];
+        let mut array = vec![0; nonterminals_len];
 
         for (r,s) in state.goto.iter() {
             array[*r] = *s;
@@ -183,8 +397,201 @@ enum Formula{
     actions+= "\t];\n\n";
     gotos+= "\t];\n\n";
 
+    // nonterminal and length of each reduction
+    let mut lengths = format!("\tconst REDUCTIONS: [(usize, usize); {}] = [\n", automaton.reductions.len());
+    for r in automaton.reductions.iter() {
+        lengths += format!("\t\t({}, {}),\n", r.nonterminal, r.len).as_str();
+    }
+    lengths+= "\t];\n\n";
+
+    // names and expected terminals for error messages
+    let mut names = format!("\tconst TOKEN_NAMES: [&'static str; {}] = [\n", terminals_len);
+    for t in automaton.terminals.iter() {
+        names += format!("\t\t{:?},\n", t.to_string()).as_str();
+    }
+    names+= "\t];\n\n";
+    if options.cst {
+        names += format!("\tconst NONTERMINAL_NAMES: [&'static str; {}] = [\n", nonterminals_len).as_str();
+        for n in automaton.nonterminals.iter() {
+            names += format!("\t\t{:?},\n", n).as_str();
+        }
+        names+= "\t];\n\n";
+    }
+    let mut expected = format!("\tconst EXPECTED: [&'static [usize]; {}] = [\n", automaton.states.len());
+    for state in automaton.states.iter() {
+        expected += format!("\t\t&{:?},\n", state.expected()).as_str();
+    }
+    expected+= "\t];\n\n";
+
+    // terminals to try where a literal terminal is not expected, the lexed text stays the same
+    let mut alternatives = format!("\tconst ALTERNATIVES: [&'static [usize]; {}] = [\n", terminals_len);
+    for a in lexer.alternatives() {
+        alternatives += format!("\t\t&{:?},\n", a).as_str();
+    }
+    alternatives+= "\t];\n\n";
+
+    // lexer mode changes per mode and terminal
+    let mut transitions = format!("\tconst TRANSITIONS: [ [isize; {}]; {}] = [\n", terminals_len + lexer.skip.len(), lexer.modes.len());
+    for mode in lexer.modes.iter() {
+        let array: Vec<_> = mode.transitions.iter().map(encode_transition).collect();
+        transitions += format!("\t\t{:?},\n", array).as_str();
+    }
+    transitions+= "\t];\n\n";
+
     content += actions.as_str();
     content += gotos.as_str();
+    content += lengths.as_str();
+    content += names.as_str();
+    content += expected.as_str();
+    content += alternatives.as_str();
+    content += transitions.as_str();
+    if lexer.indent {
+        // terminal index of each synthetic terminal, 0 where the grammar does not use it
+        for s in [parser::Synthetic::Indent, parser::Synthetic::Dedent, parser::Synthetic::Newline] {
+            let index = automaton.terminals.iter().position(|t| *t == lr::Token::Synthetic(s)).unwrap_or(0);
+            content += format!("\tconst {}: usize = {};\n", s, index).as_str();
+        }
+        content += "\n";
+    }
+
+    // the table lexer only produces the terminals of the current state, with indentation
+    // synthetic terminals come in between and the state the token is read in is not known
+    let next = match (options.table_lexer, lexer.indent) {
+        (true, false) => "{
+				let state = *self.state_stack.last().unwrap();
+				self.lexer.next(|t| t >= Self::TOKEN_NAMES.len() || Self::ACTION[state][t] != 0)
+			}",
+        (true, true) => "self.lexer.next(|_| true)",
+        (false, _) => "self.lexer.next()"
+    };
+    content += r#"	fn location(&self) -> (usize, usize) {
+		self.location_at(self.lexer.span().start)
+	}
+
+	fn location_at(&self, offset: usize) -> (usize, usize) {
+		let before = &self.lexer.source()[..offset];
+		let line = before.matches('\n').count() + 1;
+		let column = before.len() - before.rfind('\n').map_or(0, |i| i+1) + 1;
+		(line, column)
+	}
+
+	fn error(&self, state: usize, token: usize) -> ParseError {
+		let (line, column) = self.location();
+		let expected = Self::EXPECTED[state].iter().map(|t| Self::TOKEN_NAMES[*t]).collect();
+		let text = if token == 0 {String::new()} else {self.lexer.slice().to_string()};
+		ParseError::UnexpectedToken{line, column, token: Self::TOKEN_NAMES[token], text, expected}
+	}
+
+	// read the next terminal and apply its mode change, a pop in the outermost mode is ignored
+	fn next_token(&mut self) -> Result<usize, ParseError> {
+		loop {
+			let token = match self.lexer.next() {
+				Some(Ok(t)) => t,
+				Some(Err(_)) => {
+					let (line, column) = self.location();
+					return Err(ParseError::UnexpectedInput{line, column, text: self.lexer.slice().to_string()});
+				}
+				None => return Ok(0)
+			};
+			match Self::TRANSITIONS[*self.modes.last().unwrap()][token] {
+				0 => {}
+				-1 => if self.modes.len()>1 {
+					self.modes.pop();
+					self.lexer.switch(*self.modes.last().unwrap());
+				}
+				m => {
+					self.modes.push((m-1) as usize);
+					self.lexer.switch((m-1) as usize);
+				}
+			}
+			// skipped tokens
+			if token < Self::TOKEN_NAMES.len() {
+				return Ok(token);
+			}
+"#.replacen("fn next_token", if lexer.indent {"fn next_lexed"} else {"fn next_token"}, 1)
+        .replacen("self.lexer.next()", next, 1).as_str();
+    if options.cst {
+        content += "\t\t\tself.trivia.push(Trivia{token, text: self.lexer.slice(), range: self.lexer.span()});\n";
+    }
+    content += r#"		}
+	}
+
+"#;
+    if lexer.indent {
+        content += r#"	// the lexed token preceded by NEWLINE after the last token of a line
+	// and INDENT or DEDENTs for the indentation of the new line in bytes
+	fn next_token(&mut self) -> Result<usize, ParseError> {
+		if let Some(token) = self.pending.pop() {
+			return Ok(token);
+		}
+		let token = self.next_lexed()?;
+		let source = self.lexer.source();
+		let start = if token == 0 {source.len()} else {self.lexer.span().start};
+		let mut synthetic = Vec::new();
+		let mut emit = |t: usize| if t != 0 {synthetic.push(t)};
+		let line_start = match self.line_end {
+			Some(end) => source[end..start].rfind('\n').map(|i| end+i+1),
+			None => Some(0)
+		};
+		if token == 0 {
+			if self.line_end.is_some() {
+				emit(Self::NEWLINE);
+			}
+			while self.indents.len()>1 {
+				self.indents.pop();
+				emit(Self::DEDENT);
+			}
+		} else if let Some(line_start) = line_start {
+			if self.line_end.is_some() {
+				emit(Self::NEWLINE);
+			}
+			let indent = start-line_start;
+			if indent > *self.indents.last().unwrap() {
+				self.indents.push(indent);
+				emit(Self::INDENT);
+			}
+			while indent < *self.indents.last().unwrap() {
+				self.indents.pop();
+				emit(Self::DEDENT);
+			}
+			if indent != *self.indents.last().unwrap() {
+				let (line, column) = self.location();
+				return Err(ParseError::Indentation{line, column});
+			}
+		}
+		if token != 0 {
+			self.line_end = Some(self.lexer.span().end);
+		}
+		self.pending = synthetic;
+		self.pending.push(token);
+		self.pending.reverse();
+		Ok(self.pending.pop().unwrap())
+	}
+
+"#;
+    }
+    if options.cst {
+        content += r#"	// leaf of the current token, eof is an empty token at the end of the input
+	fn leaf(&mut self, terminal: usize) -> Cst<'a> {
+		let (text, range) = if terminal == 0 {
+			let end = self.lexer.source().len();
+			("", end..end)
+		} else {
+			(self.lexer.slice(), self.lexer.span())
+		};
+"#;
+        if lexer.indent {
+            content += r#"		// synthetic terminals are empty and leave the trivia to the lexed token
+		if terminal != 0 && !self.pending.is_empty() {
+			return Cst::Token{terminal, text: "", range: range.start..range.start, trivia: Vec::new()};
+		}
+"#;
+        }
+        content += r#"		Cst::Token{terminal, text, range, trivia: std::mem::take(&mut self.trivia)}
+	}
+
+"#;
+    }
 
 
     // reductions
@@ -197,17 +604,47 @@ enum Formula{
         }
     };
 
-    let _ = get_type("&str".into());
-
-
     let mut reductions = String::new();
-    for (i, r) in automaton.reductions.iter().enumerate() {
+    if options.cst {
+        let _ = get_type("Cst<'a>".into());
+        // every reduction builds a node of its children
+        reductions += r#"			task @ _ if task < 0 => {
+				let (nonterminal, len) = Self::REDUCTIONS[-(task+1) as usize];
+				let children: Vec<Cst> = parser.parse_stack.split_off(parser.parse_stack.len()-len).into_iter()
+					.map(|c| match c {Types::T1(c) => c})
+					.collect();
+				let range = match (children.first(), children.last()) {
+					(Some(first), Some(last)) => first.range().start..last.range().end,
+					_ => {
+						let start = if token == 0 {parser.lexer.source().len()} else {parser.lexer.span().start};
+						start..start
+					}
+				};
+				push!(parser, T1, Cst::Node{nonterminal, range, children});
+			}
+"#;
+    } else {
+        let _ = get_type("&str".into());
+    }
+
+    // a cst is built without running the actions
+    for (i, r) in automaton.reductions.iter().enumerate().filter(|_| !options.cst) {
         if let Some(task) = &r.task {
             let ret = get_type(task.return_type.clone());
+            // components are bound whether the action uses them or not
+            content += "\t#[allow(unused_mut, unused_variables)]\n";
             content += format!("\tfn reduction{}(", i).as_str();
 
             let mut args = String::new();
             reductions+=format!("\t\t\t{} => {{\n", - (i as isize) -1).as_str();
+            if members {
+                content += "members: &mut Members, ";
+                args += "&mut parser.members, ";
+            }
+            if let Some(ctx) = context {
+                content += format!("ctx: &mut {}, ", ctx).as_str();
+                args += "ctx, ";
+            }
 
             for (i, a) in task.args.iter().enumerate() {
                 if let Some(arg) = a {
@@ -216,12 +653,20 @@ enum Formula{
                     args += format!("a{}, ", i).as_str();
                 }
             }
-            content.pop();
-            content.pop();
-
-            args.pop();
-            args.pop();
-            content+= format!(") -> {} {} \n", &task.return_type, &task.code).as_str();
+            // actions without arguments have no separator to drop
+            if content.ends_with(", ") {
+                content.truncate(content.len()-2);
+            }
+            if args.ends_with(", ") {
+                args.truncate(args.len()-2);
+            }
+            let code = members_code(&task.code, &ast.members, "members.");
+            if task.fallible {
+                // ? in the action converts its errors into ActionError
+                content+= format!(") -> Result<{}, ActionError> {} \n", task.return_type, code).as_str();
+            } else {
+                content+= format!(") -> {} {} \n", task.return_type, code).as_str();
+            }
 
             for (i, a) in task.args.iter().enumerate().rev() {
                 if let Some(arg) = a {
@@ -229,90 +674,136 @@ enum Formula{
                 } else {
                     reductions += "\t\t\t\tlet _ = parser.parse_stack.pop();\n";
                 }
-                reductions += "\t\t\t\tlet _ = parser.state_stack.pop();\n";
             }
 
-            reductions+=format!("\t\t\t\tpush!(parser, T{}, Self::reduction{}({}));\n\t\t\t}}\n", ret, i, args).as_str();
+            if task.fallible {
+                // the error is reported at the first token of the production, an empty one at the lookahead
+                let start = if r.len == 0 {"parser.lexer.span().start".to_string()} else {format!("parser.positions[parser.positions.len()-{}]", r.len)};
+                reductions+=format!(r#"				let value = match Self::reduction{}({}) {{
+					Ok(value) => value,
+					Err(error) => {{
+						let (line, column) = parser.location_at({});
+						return Err(ParseError::Action{{line, column, error}});
+					}}
+				}};
+				push!(parser, T{}, value);
+			}}
+"#, i, args, start, ret).as_str();
+            } else {
+                reductions+=format!("\t\t\t\tpush!(parser, T{}, Self::reduction{}({}));\n\t\t\t}}\n", ret, i, args).as_str();
+            }
         }else {
-            reductions+= &format!("\t\t\t{} => {{}}\n", -(i as isize) -1);
+            // without code the value of the first component is passed on
+            reductions+= &format!("\t\t\t{} => {{\n", -(i as isize) -1);
+            for _ in 1..r.len {
+                reductions += "\t\t\t\tlet _ = parser.parse_stack.pop();\n";
+            }
+            reductions += "\t\t\t}\n";
         }
     }
 
-    let export_type =automaton.export.clone().unwrap_or("".into());
+    let export_type = if options.cst {"Cst<'a>".into()} else {automaton.export.clone().unwrap_or("".into())};
+    let (shift, accept) = if options.cst {
+        ("let leaf = parser.leaf(token);\n                    push!(parser, T1, leaf);", r#"
+        // the eof token holds the trailing trivia
+        let eof = parser.leaf(0);
+        if let Some(Types::T1(Cst::Node{children, ..})) = parser.parse_stack.last_mut() {
+            children.push(eof);
+        }"#)
+    } else {
+        (if lexer.indent {
+            // synthetic terminals have no text
+            "push!(parser, T1, if parser.pending.is_empty() {parser.lexer.slice()} else {\"\"});"
+        } else {
+            "push!(parser, T1, parser.lexer.slice());"
+        }, "")
+    };
 
+    let mut fields = String::new();
+    if options.cst {
+        fields += "trivia: vec![],\n            ";
+    }
+    if lexer.indent {
+        fields += "pending: vec![],\n            indents: vec![0],\n            line_end: None,\n            ";
+    }
+    if members {
+        fields += "members: Members::default(),\n            ";
+    }
+    let mut shift = shift.to_string();
+    let mut reduce = String::new();
+    if fallible {
+        fields += "positions: vec![],\n            ";
+        shift += "\n                    parser.positions.push(parser.lexer.span().start);";
+        reduce += r#"
+            let start = if len==0 {parser.lexer.span().start} else {parser.positions[parser.positions.len()-len]};
+            parser.positions.truncate(parser.positions.len()-len);
+            parser.positions.push(start);"#;
+    }
+
+    let (result_type, result) = if members {
+        (format!("({}, Members)", export_type), "Ok((s, parser.members))")
+    } else {
+        (export_type.to_string(), "Ok(s)")
+    };
     content += format!(r#"
-    fn parse(lex: logos::Lexer<'a, Token>) -> {} {{
+    fn parse(source: &'a str{}) -> Result<{}, ParseError> {{
         let mut parser = Self{{
             parse_stack: vec![],
             state_stack: vec![0],
-            lexer: lex
+            lexer: {},
+            {}modes: vec![0]
         }};
 
-        let mut token = match parser.lexer.next() {{
-            Some(Ok(t)) => t as usize,
-            Some(Err(e)) => panic!("{{:?}}", e),
-            None => 0
-        }};
+        let mut token = parser.next_token()?;
 
-        while parser.state_stack.len()>0 {{
+        loop {{
             let state = *parser.state_stack.last().unwrap();
-            println!("stack: {{:?}}", parser.parse_stack);
-            println!("stack: {{:?}}", parser.state_stack);
-            println!("got: {{}}:{{}}", state, token.clone() as usize);
-            let task = Self::ACTION[state][token];
-            println!("task: {{}}", task);
+            let mut task = Self::ACTION[state][token];
+            if task == 0 {{
+                if let Some(t) = Self::ALTERNATIVES[token].iter().find(|t| Self::ACTION[state][**t] != 0) {{
+                    token = *t;
+                    task = Self::ACTION[state][token];
+                }}
+            }}
             match task {{
-                0 => break,
+                0 => return Err(parser.error(state, token)),
+                1 => break,
 {}
-                new_state @ _ => {{
-                    parser.state_stack.push((new_state-1) as usize);
-                    push!(parser, T1, parser.lexer.slice());
-                    token = match parser.lexer.next() {{
-                        Some(Ok(t)) => t as usize,
-            Some(Err(e)) =>{{
-                let mut line=0;
-                let mut offset=0;
-                let span = parser.lexer.span();
-                for c in parser.lexer.source()[0..span.end].chars(){{
-                    if(c=='\n'){{
-                        offset=0;
-                        line+=1;
-                    }}
-                }}
-
-                panic!("Unexpected Token {{:?}} ({{:?}}) at {{}}:{{}}", e, parser.lexer.slice(), line, offset);
-            }},
-                        None => 0
-                    }};
+                new_state => {{
+                    parser.state_stack.push((new_state-2) as usize);
+                    {}
+                    token = parser.next_token()?;
                     continue;
                 }}
             }}
-            while parser.state_stack.len()>0 {{
-                let prev = *parser.state_stack.last().unwrap();
-                let next = Self::GOTO[prev][-(task+1) as usize];
-                if next!=0 {{
-                    parser.state_stack.push(next);
-                    break
-                }}
-                parser.state_stack.pop();
-            }}
-        }}
-        if parser.state_stack.len() != 0 {{
-            panic!("Parsing failed! {{:?}} {{:?}}", parser.parse_stack, parser.state_stack);
-        }} else {{
-            match parser.parse_stack.into_iter().nth(0).unwrap() {{
-                Types::T{}(s) => s,
-                t@ _ => panic!("Parsing failed! {{:?}}", t)
-
-            }}
+            let (nonterminal, len) = Self::REDUCTIONS[-(task+1) as usize];
+            parser.state_stack.truncate(parser.state_stack.len()-len);
+            let prev = *parser.state_stack.last().unwrap();
+            parser.state_stack.push(Self::GOTO[prev][nonterminal]);{}
+        }}{}
+        match parser.parse_stack.pop() {{
+            Some(Types::T{}(s)) => {},
+            _ => unreachable!()
         }}
     }}
-"#, export_type.clone(), reductions, get_type(export_type)).as_str();
+"#, context.map_or(String::new(), |ctx| format!(", ctx: &mut {}", ctx)), result_type, if options.table_lexer {"Lexer::new(source)"} else {"Lexers::M0(Token::lexer(source))"},
+        fields, reductions, shift, reduce, accept, get_type(export_type.clone()), result).as_str();
 
     content += "}\n\n";
-    // types
 
-    content += "#[derive(Debug)]";
+    // borrowing exports live as long as the source
+    let lifetime = if export_type.contains("'a") {"<'a>"} else {""};
+    let source = if export_type.contains("'a") {"&'a str"} else {"&str"};
+    let (ctx, arg) = match context {
+        Some(ctx) => (format!(", ctx: &mut {}", ctx), ", ctx"),
+        None => (String::new(), "")
+    };
+    let comment = if members {"// parse the whole source into the export type of the start rule and the members the actions left"} else {"// parse the whole source into the export type of the start rule"};
+    content += format!("{}\npub fn parse{}(source: {}{}) -> Result<{}, ParseError> {{\n\tParser::parse(source{})\n}}\n\n", comment, lifetime, source, ctx, result_type, arg).as_str();
+    // types in the order they were numbered
+    let mut types: Vec<_> = types.into_iter().collect();
+    types.sort_by_key(|(_, i)| *i);
+
     content+= "enum Types<'a> {";
     for (t, i) in types.iter() {
         if t.starts_with("&") {
@@ -325,97 +816,466 @@ enum Formula{
     }
     content.pop();
     content+= "\n}\n\n";
+    for e in ast.epilogue.iter() {
+        content += e;
+        content += "\n";
+    }
 
-    content += r#"
-use std::fs::read_to_string;
-fn main() {
-    let source = match read_to_string("gramma.g") {
-        Ok(s) => "\\ex x=x",
-        Err(e) => {
-            panic!("cannot read file!")
+    content
+}
+
+// longest match lexer of every mode driven by the tables of its dfa, with the interface
+// of the logos lexers except that next only produces the tokens the parser expects
+fn table_lexer(automaton: &automaton::Automaton, lexer: &Lexer) -> String {
+    let mut ranges = format!("\tconst CHAR_RANGES: [&'static [(u32, u32, usize)]; {}] = [\n", lexer.modes.len());
+    let mut starts = Vec::new();
+    let mut rows = Vec::new();
+    let mut matches = Vec::new();
+    let width = lexer.modes.iter().map(|m| m.dfa.classes().count).max().unwrap_or(0);
+    for mode in lexer.modes.iter() {
+        let classes = mode.dfa.classes();
+        let array: Vec<_> = classes.ranges.iter().map(|((lo, hi), class)| (*lo, *hi, *class)).collect();
+        ranges += format!("\t\t// mode {}\n\t\t&{:?},\n", mode.name, array).as_str();
+
+        // states of all modes are numbered consecutively
+        let offset = rows.len();
+        starts.push(offset);
+        for row in mode.dfa.table(&classes) {
+            let mut array = vec![None; width];
+            for (class, target) in row.iter().enumerate() {
+                array[class] = target.map(|t| t + offset);
+            }
+            rows.push(array);
         }
-    };
-    // println!("Input: {:?}", &string);
-    let lex = Token::lexer(&source);
-    println!("Result: {:?}", Parser::parse(lex));
-}"#;
+        matches.extend(mode.dfa.states.iter().map(|s| s.matches.iter().map(|t| mode.tokens[*t]).collect::<Vec<_>>()));
+    }
+    ranges += "\t];\n\n";
+
+    let mut table = format!("\tconst DFA_TABLE: [ [Option<usize>; {}]; {}] = [\n", width, rows.len());
+    for row in rows.iter() {
+        table += format!("\t\t{:?},\n", row).as_str();
+    }
+    table += "\t];\n\n";
+
+    let mut content = String::new();
+    for (i,t) in automaton.terminals.iter().chain(lexer.skip.iter()).enumerate() {
+        content += format!("// {}: {}\n", i, t).as_str();
+    }
+    content += r#"// table driven lexer, switching modes keeps the position
+struct Lexer<'a> {
+	source: &'a str,
+	span: std::ops::Range<usize>,
+	mode: usize,
+}
+
+impl<'a> Lexer<'a> {
+	// code point ranges (first, last, char class) of every mode, sorted
+"#;
+    content += ranges.as_str();
+    content += format!("\tconst DFA_START: [usize; {}] = {:?};\n\n", starts.len(), starts).as_str();
+    content += "\t// transitions per state and char class\n";
+    content += table.as_str();
+    content += "\t// tokens matching in each state, highest priority first\n";
+    content += format!("\tconst DFA_MATCHES: [&'static [usize]; {}] = [\n", matches.len()).as_str();
+    for m in matches.iter() {
+        content += format!("\t\t&{:?},\n", m).as_str();
+    }
+    content += "\t];\n";
+    content += r#"
+	fn new(source: &'a str) -> Self {
+		Self{source, span: 0..0, mode: 0}
+	}
+
+	// longest match of an expected token in the current mode, the longest match of any token
+	// if none is expected, unmatched input is an error of one char
+	fn next(&mut self, expected: impl Fn(usize) -> bool) -> Option<Result<usize, ()>> {
+		let start = self.span.end;
+		let rest = &self.source[start..];
+		// at the end the span is empty, errors point behind the last token
+		self.span = start..start;
+		let first = rest.chars().next()?;
+		let ranges = Self::CHAR_RANGES[self.mode];
+		let mut state = Self::DFA_START[self.mode];
+		let mut matched = None;
+		let mut unexpected = None;
+		for (i, c) in rest.char_indices() {
+			let code = c as u32;
+			let class = match ranges.binary_search_by(|(lo, hi, _)| {
+				if *hi < code {std::cmp::Ordering::Less} else if *lo > code {std::cmp::Ordering::Greater} else {std::cmp::Ordering::Equal}
+			}) {
+				Ok(r) => ranges[r].2,
+				Err(_) => break
+			};
+			state = match Self::DFA_TABLE[state][class] {
+				Some(s) => s,
+				None => break
+			};
+			let end = start+i+c.len_utf8();
+			if let Some(token) = Self::DFA_MATCHES[state].iter().find(|t| expected(**t)) {
+				matched = Some((*token, end));
+			}
+			if let Some(token) = Self::DFA_MATCHES[state].first() {
+				unexpected = Some((*token, end));
+			}
+		}
+		match matched.or(unexpected) {
+			Some((token, end)) => {
+				self.span = start..end;
+				Some(Ok(token))
+			}
+			None => {
+				self.span = start..start+first.len_utf8();
+				Some(Err(()))
+			}
+		}
+	}
 
+	fn slice(&self) -> &'a str {
+		&self.source[self.span.clone()]
+	}
+
+	fn span(&self) -> std::ops::Range<usize> {
+		self.span.clone()
+	}
+
+	fn source(&self) -> &'a str {
+		self.source
+	}
+
+	fn switch(&mut self, mode: usize) {
+		self.mode = mode;
+	}
+}
+
+"#;
     content
 }
 
-pub fn export_cpp(automaton: &automaton::Automaton) -> String {
+pub fn export_cpp(ast: &parser::GAst, automaton: &automaton::Automaton, lexer: &Lexer, options: &Options) -> String {
 
     let mut content = String::from(r#"#include "FO.hpp"
 #include <string>
-#include <cstring>
+#include <cstdint>
+#include <algorithm>
 #include <vector>
 #include <iostream>
 #include <iterator>
 #include <variant>
+#include <stdexcept>
+#include <ranges>
+#include <concepts>
 using std::string_view;
+"#);
+    for p in ast.prelude.iter() {
+        content += p;
+        content += "\n";
+    }
+    content += r#"// errors of lex and parse, what() is "line:column: message"
+struct ParseError : std::runtime_error {
+    long line, column;
+    ParseError(long line, long column, const std::string &message)
+        : std::runtime_error(std::to_string(line) + ":" + std::to_string(column) + ": " + message), line(line), column(column) {}
+};
 struct Token {
     enum Kind {
-"#);
+"#;
 
     let mut to_strs = "const char* to_str[] = {\n".to_owned();
-    let mut lexing = r#"
-auto read_token(std::vector<Token> &v, std::string_view str) -> int
-{
-    if(str[0]==' ') return 1;
-"#.to_owned();
-
-    //generate Regex
-    for (i,t) in automaton.terminals.iter().enumerate() {
-        let mut name;
+    for (i,t) in automaton.terminals.iter().chain(lexer.skip.iter()).enumerate() {
         match t {
+            lr::Token::Regex(r) if i >= automaton.terminals.len() => {
+                content+= format!("\t\t//Skip: {}\n", r).as_str();
+            }
+            lr::Token::Terminal(t) | lr::Token::Insensitive(t) if i >= automaton.terminals.len() => {
+                content+= format!("\t\t//Skip: {}\n", t).as_str();
+            }
             lr::Token::Regex(r) => {
                 let mut string = r.to_string();
                 string.remove(0);
                 content+= format!("\t\t//Regex: {}\n", string).as_str();
-                name = format!("Regex({})", r).replace("\"", "\\\"");
             }
-            lr::Token::Terminal(t) => {
+            lr::Token::Terminal(t) | lr::Token::Insensitive(t) => {
                 content+= format!("\t\t//Token: {}\n", t).as_str();
-                let fixed = t.replace("\\", "\\\\");
-                lexing += format!("\tif(str.starts_with({})){{v.emplace_back(Token::Tok{});return strlen({});}}\n", fixed,i,fixed).as_str();
-                name = format!("Token({})", t).replace("\"", "\\\"");
-
             }
             lr::Token::EOF => {
-                content+= format!("\t\t//EOF\n").as_str();
-                name = "EOF".to_owned();
+                content+= "\t\t//EOF\n";
+            }
+            lr::Token::Synthetic(s) => {
+                content+= format!("\t\t//Synthetic: {}\n", s).as_str();
             }
         }
         content+= format!("\t\tTok{}={},\n", i,i).as_str();
-        to_strs += format!("\t[Token::Tok{}] = \"{}\",\n", i, name).as_str();
+        to_strs += format!("\t[Token::Tok{}] = {},\n", i, c_string(&t.to_string())).as_str();
+    }
+    content += "\t} kind;\n\tstd::string_view data;\n\tlong line=0, column=0;\n\tsize_t offset=0;\n";
+    if options.cst {
+        content += "\t// skipped tokens in front of this one\n\tstd::vector<Token> trivia;\n";
+    }
+    content += "};\n";
+    if options.cst {
+        content += r#"
+// lossless concrete syntax tree, writing it reproduces the input
+// the root node ends with the eof token holding the trailing trivia
+struct Cst {
+    // terminal of tokens, nonterminal of nodes
+    long kind=0;
+    bool token=false;
+    // byte range without leading trivia
+    size_t begin=0, end=0;
+    std::string_view data;
+    std::vector<Token> trivia;
+    std::vector<Cst> children;
+
+    static auto leaf(const Token &t) -> Cst
+    {
+        return {t.kind, true, t.offset, t.offset+t.data.size(), t.data, t.trivia, {}};
     }
-    content += "\t} kind;\n\tstd::string_view data;\n};\n";
 
-    lexing += r#"
-    const auto *iter = str.begin();
-    while((isalnum(*iter) != 0) || *iter=='_') iter++;
-    v.emplace_back(Token::Tok?, std::string_view(str.begin(),iter));
-    return iter-str.begin();
-}
-auto lex(std::string_view inp) -> std::vector<Token>
+    void write(std::string &out) const
+    {
+        for(const Token &t : trivia) out += t.data;
+        out += data;
+        for(const Cst &c : children) c.write(out);
+    }
+};
+"#;
+    }
+
+    // dfa of every lexer mode, states of all modes are numbered consecutively
+    // class 0 holds the chars without any transition in the mode
+    let mut ranges = String::new();
+    let mut mode_ranges = vec![0];
+    let mut starts = Vec::new();
+    let mut rows = Vec::new();
+    let mut match_start = vec![0];
+    let mut matches = Vec::new();
+    let width = lexer.modes.iter().map(|m| m.dfa.classes().count+1).max().unwrap_or(1);
+    for mode in lexer.modes.iter() {
+        let classes = mode.dfa.classes();
+        ranges += format!("\t// mode {}\n", mode.name).as_str();
+        for ((lo, hi), class) in classes.ranges.iter() {
+            ranges += format!("\t{{{}, {}, {}}},\n", lo, hi, class+1).as_str();
+        }
+        // ends the search above all chars
+        ranges += "\t{0x110000, 0xffffffff, 0},\n";
+        mode_ranges.push(mode_ranges.last().unwrap() + classes.ranges.len() + 1);
+
+        let offset = rows.len() as isize;
+        starts.push(offset);
+        for row in mode.dfa.table(&classes) {
+            let mut array = vec![-1; width];
+            for (class, target) in row.iter().enumerate() {
+                array[class+1] = target.map_or(-1, |t| t as isize + offset);
+            }
+            rows.push(array);
+        }
+        for state in mode.dfa.states.iter() {
+            matches.extend(state.matches.iter().map(|t| mode.tokens[*t]));
+            match_start.push(matches.len());
+        }
+    }
+    content += format!("// code point ranges {{first, last, char class}} of every lexer mode\nconst uint32_t char_ranges[][3] = {{\n{}}};\n", ranges).as_str();
+    content += format!("const long mode_ranges[{}] = {};\n", mode_ranges.len(), format!("{:?}", mode_ranges).replace("[", "{").replace("]", "}")).as_str();
+    content += format!("const long dfa_start[{}] = {};\n", starts.len(), format!("{:?}", starts).replace("[", "{").replace("]", "}")).as_str();
+    // -1 is no transition
+    content += format!("const long dfa_table[{}][{}] = {{\n", rows.len(), width).as_str();
+    for row in rows.iter() {
+        content += format!("\t{:?},\n", row).replace("[", "{").replace("]", "}").as_str();
+    }
+    content += "};\n";
+    // tokens matching in state s, highest priority first, are dfa_matches[dfa_match_start[s]] up to dfa_matches[dfa_match_start[s+1]]
+    content += format!("const long dfa_match_start[{}] = {};\n", match_start.len(), format!("{:?}", match_start).replace("[", "{").replace("]", "}")).as_str();
+    // a dummy entry keeps the array non empty
+    matches.push(0);
+    content += format!("const long dfa_matches[{}] = {};\n", matches.len(), format!("{:?}", matches).replace("[", "{").replace("]", "}")).as_str();
+
+    // lexer mode changes per mode and terminal
+    content += format!("const long transitions[{}][{}] = {{\n", lexer.modes.len(), automaton.terminals.len() + lexer.skip.len()).as_str();
+    for mode in lexer.modes.iter() {
+        let array: Vec<_> = mode.transitions.iter().map(encode_transition).collect();
+        content += format!("\t{:?},\n", array).replace("[", "{").replace("]", "}").as_str();
+    }
+    content += "};\n";
+    // tokens from here on are skipped
+    content += format!("const long skipped = {};\n", automaton.terminals.len()).as_str();
+
+    if lexer.indent {
+        // terminal index of each synthetic terminal, 0 where the grammar does not use it
+        let index = |s| automaton.terminals.iter().position(|t| *t == lr::Token::Synthetic(s)).unwrap_or(0);
+        content += format!("const long indent_token = {}, dedent_token = {}, newline_token = {};\n",
+                           index(parser::Synthetic::Indent), index(parser::Synthetic::Dedent), index(parser::Synthetic::Newline)).as_str();
+        content += r#"
+// insert NEWLINE after the last token of a line in front of the last token
+// and INDENT or DEDENTs for the indentation of the new line in bytes
+auto indentation(std::vector<Token> &v, std::string_view source, std::vector<size_t> &indents, size_t &line_end) -> void
 {
-    std::vector<Token> v;
+    Token token = std::move(v.back());
+    v.pop_back();
+    auto emit = [&](long kind){
+        if(kind!=0) v.push_back(Token{static_cast<Token::Kind>(kind), source.substr(token.offset, 0), token.line, token.column, token.offset});
+    };
+    bool started = line_end!=std::string_view::npos;
+    size_t line_start = 0;
+    if(started){
+        line_start = source.substr(line_end, token.offset-line_end).rfind('\n');
+        if(line_start!=std::string_view::npos) line_start += line_end+1;
+    }
+    if(token.kind==Token::Tok0){
+        if(started) emit(newline_token);
+        while(indents.size()>1){
+            indents.pop_back();
+            emit(dedent_token);
+        }
+    } else if(line_start!=std::string_view::npos){
+        if(started) emit(newline_token);
+        size_t indent = token.offset-line_start;
+        if(indent>indents.back()){
+            indents.push_back(indent);
+            emit(indent_token);
+        }
+        while(indent<indents.back()){
+            indents.pop_back();
+            emit(dedent_token);
+        }
+        if(indent!=indents.back()) throw ParseError(token.line, token.column, "Dedent to an unknown indentation");
+    }
+    if(token.kind!=Token::Tok0) line_end = token.offset+token.data.size();
+    v.push_back(std::move(token));
+}
+"#;
+    }
 
-    while(inp.length()){
-        int res = read_token(v, inp);
-        if(res<1){
-            std::cout << "Error while Lexing: " << inp << "\n";
-            return {};
+    let mut lexing = String::from(r#"
+// decode the utf-8 char at the start of str, returns its length in bytes
+auto decode(std::string_view str, uint32_t &c) -> size_t
+{
+    auto first = static_cast<unsigned char>(str[0]);
+    size_t len = first<0xc0 ? 1 : first<0xe0 ? 2 : first<0xf0 ? 3 : 4;
+    if(len>str.size()) len = 1;
+    c = len==1 ? first : first & (0x7f>>len);
+    for(size_t i=1; i<len; i++) c = (c<<6) | (static_cast<unsigned char>(str[i]) & 0x3f);
+    return len;
+}
+// longest match of an expected token in the dfa of the mode, the longest match of any token if
+// none is expected, expected is the action row of the parser state or nullptr for all tokens
+// returns the length of the token or 0
+auto read_token(std::vector<Token> &v, std::string_view str, long mode, const long *expected) -> int
+{
+    long state = dfa_start[mode];
+    long kind = -1, unexpected = -1;
+    size_t length = 0, unexpected_length = 0, pos = 0;
+    while(pos<str.size()){
+        uint32_t c;
+        pos += decode(str.substr(pos), c);
+        const auto *range = std::partition_point(char_ranges+mode_ranges[mode], char_ranges+mode_ranges[mode+1],
+                                                 [c](const uint32_t (&r)[3]){return r[1]<c;});
+        state = dfa_table[state][(*range)[0]<=c ? (*range)[2] : 0];
+        if(state<0) break;
+        for(long i=dfa_match_start[state]; i<dfa_match_start[state+1]; i++){
+            long t = dfa_matches[i];
+            if(t>=skipped || !expected || expected[t]!=0){
+                kind = t;
+                length = pos;
+                break;
+            }
+        }
+        if(dfa_match_start[state]<dfa_match_start[state+1]){
+            unexpected = dfa_matches[dfa_match_start[state]];
+            unexpected_length = pos;
+        }
+    }
+    if(kind<0){
+        kind = unexpected;
+        length = unexpected_length;
+    }
+    if(kind<0) return 0;
+    v.emplace_back(static_cast<Token::Kind>(kind), str.substr(0, length));
+    return length;
+}
+// lexes on demand, so the parser can restrict every token to the terminals it expects
+class Lexer
+{
+    std::string_view source, inp;
+    // a pop in the outermost mode is ignored
+    std::vector<long> modes{0};
+"#);
+    if options.cst {
+        lexing += "    std::vector<Token> trivia;\n";
+    }
+    if lexer.indent {
+        lexing += "    std::vector<size_t> indents{0};\n    size_t line_end = std::string_view::npos;\n";
+    }
+    lexing += r#"    long line=1, column=1;
+public:
+    Lexer(std::string_view source) : source(source), inp(source) {}
+
+    // appends the next token to v, after the input the eof token
+    auto next(std::vector<Token> &v, const long *expected) -> void
+    {
+        while(inp.length()){
+            size_t count = v.size();
+            int res = read_token(v, inp, modes.back(), expected);
+            if(res<1){
+                uint32_t c;
+                throw ParseError(line, column, "Unexpected Input " + std::string(inp.substr(0, decode(inp, c))));
+            }
+            v.back().line = line;
+            v.back().column = column;
+            v.back().offset = source.length()-inp.length();
+            long change = transitions[modes.back()][v.back().kind];
+            if(change>0) modes.push_back(change-1);
+            else if(change<0 && modes.size()>1) modes.pop_back();
+"#;
+    if options.cst {
+        lexing += r#"            if(v.back().kind>=skipped){
+                trivia.push_back(std::move(v.back()));
+                v.pop_back();
+            } else v.back().trivia.swap(trivia);
+"#;
+    } else {
+        lexing += "            if(v.back().kind>=skipped) v.pop_back();\n";
+    }
+    if lexer.indent {
+        lexing += "            if(v.size()>count) indentation(v, source, indents, line_end);\n";
+    }
+    lexing += r#"            for(char c : inp.substr(0, res)){
+                if(c=='\n'){
+                    line++;
+                    column=1;
+                } else column++;
+            }
+            inp = inp.substr(res);
+            if(v.size()>count) return;
         }
-        inp = inp.substr(res);
+        v.emplace_back(Token::Tok0, inp, line, column, source.length());
+"#;
+    if options.cst {
+        lexing += "        v.back().trivia.swap(trivia);\n";
     }
-    v.emplace_back(Token::Tok0);
+    if lexer.indent {
+        lexing += "        indentation(v, source, indents, line_end);\n";
+    }
+    lexing += r#"    }
+};
+auto lex(std::string_view inp) -> std::vector<Token>
+{
+    Lexer lexer(inp);
+    std::vector<Token> v;
+    do lexer.next(v, nullptr);
+    while(v.back().kind!=Token::Tok0);
     return v;
 }
 "#;
 
     content+=lexing.as_str();
     content+=(to_strs+"\n};").as_str();
+    if options.cst {
+        content += "\nconst char* nonterminal_str[] = {\n";
+        for n in automaton.nonterminals.iter() {
+            content += format!("\t{},\n", c_string(n)).as_str();
+        }
+        content += "};";
+    }
 
     content += r#"
 template<class S>
@@ -434,16 +1294,42 @@ class Parser
         }
     };
 
-    let _ = get_type("std::string_view".into());
-
     let mut reductions = String::new();
-    for (i, r) in automaton.reductions.iter().enumerate() {
+    if options.cst {
+        let _ = get_type("Cst".into());
+        // every reduction builds a node of its children
+        for i in 0..automaton.reductions.len() {
+            reductions += format!("\t\t\tcase {}:\n", -(i as isize) -1).as_str();
+        }
+        reductions += r#"			{
+				const long *reduction = reductions[-(task+1)];
+				Cst node{reduction[0]};
+				for(auto it = data_stack.end()-reduction[1]; it!=data_stack.end(); ++it){
+					node.children.push_back(std::move(std::get<Cst>(*it)));
+				}
+				data_stack.resize(data_stack.size()-reduction[1]);
+				node.begin = node.children.empty() ? token.offset : node.children.front().begin;
+				node.end = node.children.empty() ? token.offset : node.children.back().end;
+				data_stack.emplace_back(std::move(node));
+			}
+				break;
+"#;
+    } else {
+        let _ = get_type("std::string_view".into());
+    }
+
+    // a cst is built without running the actions
+    for (i, r) in automaton.reductions.iter().enumerate().filter(|_| !options.cst) {
         if let Some(task) = &r.task {
-            let ret = get_type(task.return_type.clone());
+            get_type(task.return_type.clone());
             content += format!("\tauto reduction{}(", i).as_str();
 
             let mut args = String::new();
             reductions+=format!("\t\t\tcase {}:{{\n", - (i as isize) -1).as_str();
+            if let Some(ctx) = ast.context.as_ref() {
+                content += format!("{}& ctx, ", ctx).as_str();
+                args += "ctx, ";
+            }
 
             for (i, a) in task.args.iter().enumerate() {
                 if let Some(arg) = a {
@@ -452,12 +1338,14 @@ class Parser
                     args += format!("std::move(a{}), ", i).as_str();
                 }
             }
-            content.pop();
-            content.pop();
-
-            args.pop();
-            args.pop();
-            content+= format!(") -> {} {} \n", &task.return_type, &task.code).as_str();
+            // actions without arguments have no separator to drop
+            if content.ends_with(", ") {
+                content.truncate(content.len()-2);
+            }
+            if args.ends_with(", ") {
+                args.truncate(args.len()-2);
+            }
+            content+= format!(") -> {} {} \n", &task.return_type, members_code(&task.code, &ast.members, "this->")).as_str();
 
             for (i, a) in task.args.iter().enumerate().rev() {
                 if let Some(arg) = a {
@@ -465,17 +1353,40 @@ class Parser
                 } else {
                     reductions += "\t\t\t\tdata_stack.pop_back();\n";
                 }
-                reductions += "\t\t\t\tstate_stack.pop_back();\n";
             }
 
-            reductions+=format!("\t\t\t\tdata_stack.emplace_back(reduction{}({}));\n\t\t\t}}\n\t\t\t\tbreak;\n",
-                                i, args).as_str();
+            if task.fallible {
+                // exceptions of the action are rethrown at the first token of the production, an empty one at the lookahead
+                let start = if r.len == 0 {"std::pair<long, long>{token.line, token.column}".to_string()} else {format!("position_stack[position_stack.size()-{}]", r.len)};
+                reductions+=format!(r#"				try {{
+					data_stack.emplace_back(reduction{}({}));
+				}} catch(const std::exception &e) {{
+					auto [line, column] = {};
+					throw ParseError(line, column, e.what());
+				}}
+			}}
+				break;
+"#, i, args, start).as_str();
+            } else {
+                reductions+=format!("\t\t\t\tdata_stack.emplace_back(reduction{}({}));\n\t\t\t}}\n\t\t\t\tbreak;\n",
+                                    i, args).as_str();
+            }
         }else {
-            reductions+= &format!("\t\t\tcase {}: break;\n", -(i as isize) -1);
+            // without code the value of the first component is passed on
+            reductions+= &format!("\t\t\tcase {}:\n", -(i as isize) -1);
+            for _ in 1..r.len {
+                reductions += "\t\t\t\tdata_stack.pop_back();\n";
+            }
+            reductions += "\t\t\t\tbreak;\n";
         }
     }
 
-    let export_type =automaton.export.clone().unwrap_or("".into());
+    let export_type = if options.cst {"Cst".into()} else {automaton.export.clone().unwrap_or("".into())};
+    let (shift, accept) = if options.cst {
+        ("Cst::leaf(token)", "\n        // the eof token holds the trailing trivia\n        std::get<Cst>(data_stack.back()).children.push_back(Cst::leaf(token));")
+    } else {
+        ("token.data", "")
+    };
 
     // content += "\tstruct Type\n\t{\n\t\t";
     // let mut en: String = "enum {".to_owned();
@@ -488,8 +1399,11 @@ class Parser
     // content+= (en + "} kind;\n\t\t").as_str();
     // content+= (un + "};\n").as_str();
     // content+= "};\n\n";
+    // types in the order they were numbered
+    let mut types: Vec<_> = types.into_iter().collect();
+    types.sort_by_key(|(_, i)| *i);
     content += "\tusing Type = std::variant<";
-    for (t, i) in types.iter() {
+    for (t, _) in types.iter() {
         content += format!("{},", t).as_str();
     }
     content.pop();
@@ -497,25 +1411,24 @@ class Parser
 
     content+= "\tstd::vector<Type> data_stack;\n";
     content+= "\tstd::vector<long> state_stack;\n";
+    // errors of ?{ } actions need the start of the reduced values
+    let fallible = !options.cst && automaton.reductions.iter().any(|r| r.task.as_ref().is_some_and(|t| t.fallible));
+    if fallible {
+        content+= "\t// line and column of the first token of every value on the data stack\n";
+        content+= "\tstd::vector<std::pair<long, long>> position_stack;\n";
+    }
 
     let terminals_len = automaton.terminals.len();
-    let reductions_len = automaton.reductions.len();
+    let nonterminals_len = automaton.nonterminals.len();
 
     let mut actions = format!("\tconst long actions[{}][{}]= {{\n", automaton.states.len(), terminals_len);
-    let mut gotos = format!("\tconst long gotos[{}][{}] = {{\n", automaton.states.len(), reductions_len);
+    let mut gotos = format!("\tconst long gotos[{}][{}] = {{\n", automaton.states.len(), nonterminals_len);
 
     for state in automaton.states.iter() {
-        let mut array = vec![0; terminals_len];
-        for (i,a) in state.lookahead.iter() {
-            array[*i] = match a {
-                Action::Halt => 0,
-                Action::Reduce(i) => - (*i as isize) -1,
-                Action::Shift(i) => *i as isize +1
-            }
-        }
+        let array: Vec<_> = (0..terminals_len).map(|t| encode_action(&state.action(t))).collect();
         actions += format!("\t\t{:?}, \n", array).as_str().replace("[", "{").as_str().replace("]", "}").as_str();
 
-        let mut array = vec![0; reductions_len];
+        let mut array = vec![0; nonterminals_len];
 
         for (r,s) in state.goto.iter() {
             array[*r] = *s;
@@ -526,60 +1439,204 @@ class Parser
     actions+= "\t};\n\n";
     gotos+= "\t};\n\n";
 
+    // nonterminal and length of each reduction
+    let mut lengths = format!("\tconst long reductions[{}][2] = {{\n", automaton.reductions.len());
+    for r in automaton.reductions.iter() {
+        lengths += format!("\t\t{{{}, {}}},\n", r.nonterminal, r.len).as_str();
+    }
+    lengths+= "\t};\n\n";
+
+    // expected terminals for error messages
+    let mut expected = format!("\tconst std::vector<long> expected[{}] = {{\n", automaton.states.len());
+    for state in automaton.states.iter() {
+        expected += format!("\t\t{:?},\n", state.expected()).replace("[", "{").replace("]", "}").as_str();
+    }
+    expected+= "\t};\n\n";
+
+    // terminals to try where a literal terminal is not expected, the lexed text stays the same
+    let mut alternatives = format!("\tconst std::vector<long> alternatives[{}] = {{\n", terminals_len);
+    for a in lexer.alternatives() {
+        alternatives += format!("\t\t{:?},\n", a).replace("[", "{").replace("]", "}").as_str();
+    }
+    alternatives+= "\t};\n\n";
+
     content += actions.as_str();
     content += gotos.as_str();
+    content += lengths.as_str();
+    content += expected.as_str();
+    content += alternatives.as_str();
 
 
-    content += format!(r#"
-public:
-    template<std::ranges::range R>
-    auto parse(R tokens) -> {}
+    content += "\npublic:\n";
+    if !options.cst {
+        // $member fields the actions share, reset by every parse and readable after it
+        for m in ast.members.iter() {
+            content += format!("\t{} {}{{}};\n", m.member_type, m.name).as_str();
+        }
+    }
+    let (mut clear, shift_position, reduce_position) = if fallible {
+        ("\n        position_stack.clear();".to_string(), "\n                    position_stack.emplace_back(token.line, token.column);", r#"
+            auto start = reduction[1]==0 ? std::pair<long, long>{token.line, token.column} : position_stack[position_stack.size()-reduction[1]];
+            position_stack.resize(position_stack.size()-reduction[1]);
+            position_stack.push_back(start);"#)
+    } else {
+        (String::new(), "", "")
+    };
+    for m in ast.members.iter().filter(|_| !options.cst) {
+        clear += format!("\n        {} = {{}};", m.name).as_str();
+    }
+    let context = ast.context.as_ref().filter(|_| !options.cst);
+    let (ctx_param, ctx_arg) = match context {
+        Some(ctx) => (format!(", {}& ctx", ctx), ", ctx"),
+        None => (String::new(), "")
+    };
+    // with indentation synthetic terminals come in between, the state the token is read in is not known
+    let expected_row = if lexer.indent {"nullptr"} else {"actions[state]"};
+    content += format!(r#"    template<std::ranges::range R> requires std::same_as<std::ranges::range_value_t<R>, Token>
+    auto parse(R tokens{ctx_param}) -> {export_type}
     {{
         auto start = tokens.begin();
         auto end = tokens.end();
-        if(start==end) return {{}};
+        return run([&](long){{ return start==end ? Token{{Token::Tok0}} : *start++; }}{ctx_arg});
+    }}
+
+    // lexes while parsing, so every token is one of the terminals the parser expects where possible
+    auto parse(std::string_view source{ctx_param}) -> {export_type}
+    {{
+        Lexer lexer(source);
+        std::vector<Token> tokens;
+        size_t next = 0;
+        return run([&](long state){{
+            if(next==tokens.size()){{
+                tokens.clear();
+                next = 0;
+                lexer.next(tokens, {expected_row});
+            }}
+            return std::move(tokens[next++]);
+        }}{ctx_arg});
+    }}
 
+private:
+    template<class F>
+    auto run(F next_token{ctx_param}) -> {export_type}
+    {{
         state_stack = {{0}};
-        data_stack.clear();
+        data_stack.clear();{}
 
-        auto token = *start++;
+        Token token = next_token(0);
 
-        while(!state_stack.empty()) {{
+        while(true) {{
             long state = state_stack.back();
             long task = actions[state][token.kind];
+            if(task==0){{
+                for(long t : alternatives[token.kind]){{
+                    if(actions[state][t]!=0){{
+                        token.kind = static_cast<Token::Kind>(t);
+                        task = actions[state][t];
+                        break;
+                    }}
+                }}
+            }}
             switch(task){{
-            case 0: goto stop;
+            case 0: goto error;
+            case 1: goto accept;
 {}
                 default: {{
-                    state_stack.push_back(task-1);
-                    data_stack.emplace_back(token.data);
-                    if(start==end) token = {{Token::Tok0}};
-                    token = *start++;
+                    state_stack.push_back(task-2);
+                    data_stack.emplace_back({});{}
+                    token = next_token(state_stack.back());
                     continue;
                 }}
             }}
-            while(!state_stack.empty()){{
-                long prev = state_stack.back();
-                long next = gotos[prev][-(task+1)];
-                if(next!=0){{
-                    state_stack.push_back(next);
-                    break;
-                }}
-                state_stack.pop_back();
-            }}
+            const long *reduction = reductions[-(task+1)];
+            state_stack.resize(state_stack.size()-reduction[1]);
+            state_stack.push_back(gotos[state_stack.back()][reduction[0]]);{}
         }}
-stop:
-        if(!state_stack.empty()){{
-            std::cout << "Failed to Parse\n";
-        }} else {{
-            return std::move(std::get<{}>(data_stack.back()));
+error:
+        {{
+            std::string message = std::string("Unexpected Token ") + to_str[token.kind] + ", expected one of ";
+            for(long t : expected[state_stack.back()]){{
+                if(t!=expected[state_stack.back()].front()) message += ", ";
+                message += to_str[t];
+            }}
+            throw ParseError(token.line, token.column, message);
         }}
-        return {{}};
+accept:{}
+        return std::move(std::get<{}>(data_stack.back()));
     }}
-"#, export_type, reductions, export_type).as_str();
+"#, clear, reductions, shift, shift_position, reduce_position, accept, export_type).as_str();
 
     content += "};\n\n";
+    for e in ast.epilogue.iter() {
+        content += e;
+        content += "\n";
+    }
     // types
 
     content
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use logos::Logos;
+    use std::process::Command;
+
+    fn generate_rust(grammar: &str, options: &Options) -> String {
+        let ast = parser::parse(parser::gTokens::lexer(grammar)).unwrap();
+        let lr = lr::LR::new(&ast.rules).unwrap();
+        let mut automaton = automaton::Automaton::new(&lr).unwrap();
+        automaton.minimize();
+        let lexer = Lexer::new(&automaton.terminals, &ast).unwrap();
+        export_rust(&ast, &automaton, &lexer, options)
+    }
+
+    // compiles the parser with a main printing the results for the inputs, the table lexer needs no crates
+    fn run_rust(name: &str, grammar: &str, inputs: &[&str]) -> Vec<String> {
+        let mut source = generate_rust(grammar, &Options{table_lexer: true, ..Options::default()});
+        source += "\nfn main() {\n";
+        for input in inputs {
+            source += &format!("\tprintln!(\"{{:?}}\", parse({:?}));\n", input);
+        }
+        source += "}\n";
+        let dir = std::env::temp_dir();
+        let (path, binary) = (dir.join(format!("{}.rs", name)), dir.join(name));
+        std::fs::write(&path, source).unwrap();
+        let rustc = std::env::var("RUSTC").unwrap_or("rustc".to_string());
+        let status = Command::new(rustc).args(["--edition", "2021", "-o"]).arg(&binary).arg(&path).status().unwrap();
+        assert!(status.success());
+        let output = Command::new(&binary).output().unwrap();
+        String::from_utf8(output.stdout).unwrap().lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn fallible_actions_convert_errors_with_question_mark() {
+        let grammar = r#"
+            %skip " ";
+            start: sum=a {a} -> u32;
+            sum: sum=a "+" byte=b ?{a.checked_add(b).ok_or("overflow".into())} | byte=a {a} -> u32;
+            byte: r"[a-z0-9]+"=s ?{ let v: u32 = s.parse()?; if v > 255 { return Err("too big".into()) } Ok(v) } -> u32;
+        "#;
+        assert_eq!(run_rust("g_fallible_actions", grammar, &["1 + 2", "1 + 300", "1 + x"]), [
+            "Ok(3)",
+            r#"Err(Action { line: 1, column: 5, error: "too big" })"#,
+            "Err(Action { line: 1, column: 5, error: ParseIntError { kind: InvalidDigit } })",
+        ]);
+    }
+
+    #[test]
+    fn table_lexer_reads_expected_terminals() {
+        let grammar = r#"
+            %skip " ";
+            start: expr=a {a} -> String;
+            expr: ty=t {t} | expr=a ">>" r"[0-9]+"=n {format!("({}>>{})", a, n)} -> String;
+            ty: r"[a-z]+"=n {n.to_string()} | r"[a-z]+"=n "<" ty=t ">" {format!("{}<{}>", n, t)} -> String;
+        "#;
+        assert_eq!(run_rust("g_expected_terminals", grammar, &["a<b<c>>", "a<b<c>> >> 3", "a<b>>>3", "a<b>>>>3"]), [
+            r#"Ok("a<b<c>>")"#,
+            r#"Ok("(a<b<c>>>>3)")"#,
+            r#"Ok("(a<b>>>3)")"#,
+            r#"Err(UnexpectedToken { line: 1, column: 7, token: "\">\"", text: ">", expected: ["r\"[0-9]+\""] })"#,
+        ]);
+    }
+}